@@ -0,0 +1,100 @@
+//! Choose which steps of an optimization to save as frames.
+
+use std::str::FromStr;
+
+/// Which steps to save, besides the last, which is always saved.
+///
+/// These can be parsed from a string in one of these forms:
+///
+/// - `every:10` for `Every`.
+/// - `log` or `log:4` for `Log`, with one frame per doubling unless given.
+/// - `drops:0.01` for `Drops`.
+/// - `count:100` for `Count`.
+/// - `final` for `Final`.
+#[derive(Clone, Copy, Debug)]
+pub enum Capture {
+    /// Every this many steps, starting from the first.
+    Every(usize),
+
+    /// This many steps for each doubling of the number of steps, starting from the first. With one,
+    /// that's the first step and every power of two after it.
+    Log(usize),
+
+    /// The first step, and then whenever the objective has dropped by at least this fraction since
+    /// the last frame.
+    Drops(f64),
+
+    /// About this many steps in all, spread evenly from the first to the last. The last isn't known
+    /// until the end, so until then, every other step is kept, then every fourth, and so on, to keep
+    /// at most twice this many, and each frame is the kept step nearest to where it should be.
+    Count(usize),
+
+    /// Only the last step.
+    Final,
+}
+
+impl Capture {
+    /// Whether to save step `i`, whose objective is reported as `fx`, given the objective reported
+    /// with the last step saved, if any. This is never true for `Count` and `Final`.
+    pub fn wants(&self, i: usize, fx: f64, last: Option<f64>) -> bool {
+        match *self {
+            Capture::Every(k) => i.is_multiple_of(k),
+            Capture::Log(k) => {
+                // whether some k-th root of two's power is in (i - 1, i]
+                let level = |i: usize| (k as f64 * (i as f64).log2()).floor();
+                i < 2 || level(i) > level(i - 1)
+            }
+            Capture::Drops(fraction) => last.is_none_or(|last| last - fx >= fraction * last.abs()),
+            Capture::Count(_) | Capture::Final => false,
+        }
+    }
+
+    /// For `Count`, return which of the steps from `first` to `last` inclusive to save, in order.
+    /// Otherwise, this is empty.
+    pub fn spread(&self, first: usize, last: usize) -> Vec<usize> {
+        let Capture::Count(count) = *self else {
+            return vec![];
+        };
+        let span = (last - first) as f64;
+        let mut steps: Vec<usize> = match count {
+            0 => vec![],
+            1 => vec![last],
+            _ => (0..count)
+                .map(|k| first + (k as f64 * span / (count - 1) as f64).round() as usize)
+                .collect(),
+        };
+        steps.dedup();
+        steps
+    }
+}
+
+impl FromStr for Capture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, value) = match s.trim().split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s.trim(), None),
+        };
+        let count = |what: &str| match value.map(str::parse) {
+            Some(Ok(k)) if k > 0 => Ok(k),
+            _ => Err(format!("{s:?} should be {name}: followed by {what}")),
+        };
+        match name {
+            "every" => Ok(Capture::Every(count("a positive number of steps")?)),
+            "log" if value.is_none() => Ok(Capture::Log(1)),
+            "log" => Ok(Capture::Log(count("a positive number of frames")?)),
+            "drops" => match value.map(str::parse) {
+                Some(Ok(fraction)) if (0.0..1.).contains(&fraction) => Ok(Capture::Drops(fraction)),
+                _ => Err(format!(
+                    "{s:?} should be drops: followed by a fraction below one"
+                )),
+            },
+            "count" => Ok(Capture::Count(count("a positive number of frames")?)),
+            "final" if value.is_none() => Ok(Capture::Final),
+            _ => Err(format!(
+                "{s:?} should be every:K, log, log:K, drops:FRACTION, count:K or final"
+            )),
+        }
+    }
+}
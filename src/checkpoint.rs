@@ -0,0 +1,365 @@
+//! Saving an optimization part way through, so that a later run can pick it up again and take
+//! exactly the same steps it would have taken without stopping.
+//!
+//! A checkpoint is a text file with one field per line: its name, then its values separated by
+//! spaces. Numbers are written in their shortest form that reads back as the same `f64`, so nothing
+//! is lost to rounding. The optimizer's state comes last, starting with its name. For L-BFGS, its
+//! history comes at the very end, as alternating `s` and `y` lines, most recent first.
+
+use crate::{lbfgs, optimizer::State, Glyphs};
+use std::{
+    fmt::{Display, Write as _},
+    fs::{read_to_string, rename, File},
+    io::{self, Write as _},
+    path::Path,
+    str::FromStr,
+};
+
+/// Everything needed to carry on with an optimization.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The glyphs, at the current point.
+    pub glyphs: Glyphs,
+
+    /// How many steps have been taken so far.
+    pub steps: usize,
+
+    /// The objective value reported with the last step, or NaN before the first.
+    pub fx: f64,
+
+    /// How many of the last steps in a row had failed line searches.
+    pub failures: usize,
+
+    /// The optimizer's state after the last step, if there has been one.
+    pub state: Option<State>,
+}
+
+/// Append a line to `s` with the field `name` and its `values`.
+fn field<T: Display>(s: &mut String, name: &str, values: impl IntoIterator<Item = T>) {
+    s.push_str(name);
+    for value in values {
+        write!(s, " {value}").unwrap();
+    }
+    s.push('\n');
+}
+
+/// Parse `value`, which should be a single number or flag.
+fn parse_one<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| format!("{value:?}: {e}"))
+}
+
+/// Return the values on the next of `lines`, given as names and values, which should be the field
+/// `name`.
+fn next<'a>(
+    lines: &mut impl Iterator<Item = (&'a str, &'a str)>,
+    name: &str,
+) -> Result<&'a str, String> {
+    match lines.next() {
+        Some((key, values)) if key == name => Ok(values),
+        _ => Err(format!("expected {name:?}")),
+    }
+}
+
+/// Parse the space-separated values of the next field of `lines`, which should be `name`.
+fn values<'a, T: FromStr>(
+    lines: &mut impl Iterator<Item = (&'a str, &'a str)>,
+    name: &str,
+) -> Result<Vec<T>, String>
+where
+    T::Err: Display,
+{
+    let values = next(lines, name)?.split_whitespace().map(parse_one);
+    values
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{name}: {e}"))
+}
+
+/// Parse the single value of the next field of `lines`, which should be `name`.
+fn value<'a, T: FromStr>(
+    lines: &mut impl Iterator<Item = (&'a str, &'a str)>,
+    name: &str,
+) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    parse_one(next(lines, name)?).map_err(|e| format!("{name}: {e}"))
+}
+
+/// Like `values`, but for a vector of the `len` coordinates.
+fn vector<'a>(
+    lines: &mut impl Iterator<Item = (&'a str, &'a str)>,
+    name: &str,
+    len: usize,
+) -> Result<Vec<f64>, String> {
+    let values = values(lines, name)?;
+    match values.len() == len {
+        true => Ok(values),
+        false => Err(format!(
+            "expected {len} values for {name}, not {}",
+            values.len()
+        )),
+    }
+}
+
+impl Checkpoint {
+    /// Start a new optimization of `glyphs`.
+    pub fn new(glyphs: Glyphs) -> Self {
+        Self {
+            glyphs,
+            steps: 0,
+            fx: f64::NAN,
+            failures: 0,
+            state: None,
+        }
+    }
+
+    /// Write the checkpoint to `path`, replacing whatever was there, noting that the glyph indices
+    /// refer to `chars`. It's written to a temporary file alongside first, and then moved into
+    /// place, so that the old checkpoint survives if writing is interrupted.
+    pub fn save(&self, path: &Path, chars: &str) -> io::Result<()> {
+        let mut s = String::new();
+        field(&mut s, "chars", [chars]);
+        field(&mut s, "steps", [self.steps]);
+        field(&mut s, "fx", [self.fx]);
+        field(&mut s, "failures", [self.failures]);
+        field(&mut s, "indices", &self.glyphs.indices);
+        field(&mut s, "hues", &self.glyphs.hues);
+        field(&mut s, "coords", &self.glyphs.coords);
+        if let Some(state) = &self.state {
+            field(&mut s, "optimizer", [state.name()]);
+        }
+        match &self.state {
+            None => {}
+            Some(State::Lbfgs(state)) => {
+                let counts = state.counts;
+                field(&mut s, "x", &state.x);
+                field(&mut s, "grad", &state.grad);
+                field(&mut s, "gamma", [state.gamma]);
+                field(&mut s, "reset", [state.reset]);
+                #[rustfmt::skip]
+                field(&mut s, "counts", [
+                    counts.negative, counts.skipped, counts.damped, counts.ascents, counts.failures,
+                ]);
+                for (s_j, y_j) in &state.s_y {
+                    field(&mut s, "s", s_j);
+                    field(&mut s, "y", y_j);
+                }
+            }
+            Some(State::Momentum { v }) => field(&mut s, "v", v),
+            Some(State::Adam {
+                m,
+                v,
+                beta1_t,
+                beta2_t,
+            }) => {
+                field(&mut s, "m", m);
+                field(&mut s, "v", v);
+                field(&mut s, "beta1_t", [beta1_t]);
+                field(&mut s, "beta2_t", [beta2_t]);
+            }
+            Some(State::Cg {
+                prev,
+                d,
+                slope_prev,
+                alpha,
+                found,
+            }) => {
+                field(&mut s, "prev", prev);
+                field(&mut s, "d", d);
+                field(&mut s, "slope_prev", [slope_prev]);
+                field(&mut s, "alpha", [alpha]);
+                field(&mut s, "found", [found]);
+            }
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(s.as_bytes())?;
+        file.sync_all()?;
+        rename(&temp, path)
+    }
+
+    /// Read a checkpoint written by `save`, checking that its glyph indices refer to `chars`.
+    pub fn load(path: &Path, chars: &str) -> Result<Self, String> {
+        let text = read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&text, chars).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Parse the contents of a checkpoint file, as for `load`.
+    fn parse(text: &str, chars: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(|line| line.split_once(' ').unwrap_or((line, "")))
+            .peekable();
+        let lines = &mut lines;
+        let saved = next(lines, "chars")?;
+        if saved != chars {
+            return Err(format!("saved for {saved:?}, not {chars:?}"));
+        }
+        let steps = value(lines, "steps")?;
+        let fx = value(lines, "fx")?;
+        let failures = value(lines, "failures")?;
+        let indices: Vec<usize> = values(lines, "indices")?;
+        let n = indices.len();
+        let count = chars.chars().count();
+        if let Some(i) = indices.iter().find(|&&i| i >= count) {
+            return Err(format!("index {i} should be less than {count}"));
+        }
+        let glyphs = Glyphs {
+            indices,
+            hues: vector(lines, "hues", n)?,
+            coords: vector(lines, "coords", 4 * n)?,
+        };
+        let name = match lines.peek() {
+            Some(_) => Some(next(lines, "optimizer")?),
+            None => None,
+        };
+        let state = match name {
+            None => None,
+            Some("lbfgs") => {
+                let x = vector(lines, "x", 4 * n)?;
+                let grad = vector(lines, "grad", 4 * n)?;
+                let gamma = value(lines, "gamma")?;
+                let reset = value(lines, "reset")?;
+                let [negative, skipped, damped, ascents, failures] = values(lines, "counts")?[..]
+                else {
+                    return Err("expected five counts".to_owned());
+                };
+                let mut s_y = vec![];
+                while lines.peek().is_some() {
+                    let s = vector(lines, "s", 4 * n)?;
+                    s_y.push((s, vector(lines, "y", 4 * n)?));
+                }
+                Some(State::Lbfgs(lbfgs::State {
+                    x,
+                    grad,
+                    s_y,
+                    gamma,
+                    reset,
+                    counts: lbfgs::Counts {
+                        negative,
+                        skipped,
+                        damped,
+                        ascents,
+                        failures,
+                    },
+                }))
+            }
+            Some("momentum") => Some(State::Momentum {
+                v: vector(lines, "v", 4 * n)?,
+            }),
+            Some("adam") => Some(State::Adam {
+                m: vector(lines, "m", 4 * n)?,
+                v: vector(lines, "v", 4 * n)?,
+                beta1_t: value(lines, "beta1_t")?,
+                beta2_t: value(lines, "beta2_t")?,
+            }),
+            Some("cg") => Some(State::Cg {
+                prev: vector(lines, "prev", 4 * n)?,
+                d: vector(lines, "d", 4 * n)?,
+                slope_prev: value(lines, "slope_prev")?,
+                alpha: value(lines, "alpha")?,
+                found: value(lines, "found")?,
+            }),
+            Some(name) => return Err(format!("unknown optimizer {name:?}")),
+        };
+        if let Some((key, _)) = lines.next() {
+            return Err(format!("unexpected {key:?}"));
+        }
+        Ok(Self {
+            glyphs,
+            steps,
+            fx,
+            failures,
+            state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_optimizer, lbfgs::Bounds, optimizer::Step, scene};
+    use std::env::temp_dir;
+
+    /// The Rosenbrock function, in pairs of variables.
+    fn rosenbrock(x: &[f64], grad: &mut [f64]) -> f64 {
+        let mut fx = 0.;
+        for i in (0..x.len()).step_by(2) {
+            let (a, b) = (x[i], x[i + 1]);
+            grad[i] = -2. * (1. - a) - 400. * a * (b - a * a);
+            grad[i + 1] = 200. * (b - a * a);
+            fx += (1. - a).powi(2) + 100. * (b - a * a).powi(2);
+        }
+        fx
+    }
+
+    /// Take `steps` steps with the optimizer called `name` from `start`, and return a checkpoint
+    /// after the last one.
+    fn run(name: &str, bounds: Option<Bounds>, start: Checkpoint, steps: usize) -> Checkpoint {
+        let settings = scene::Optimizer {
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        let optimizer = get_optimizer(&settings).unwrap();
+        let mut x = start.glyphs.coords.clone();
+        let mut i = start.steps;
+        let mut end = None;
+        let stop = &mut |step: Step| {
+            i += 1;
+            let done = i == steps;
+            if done {
+                end = Some(Checkpoint {
+                    glyphs: Glyphs {
+                        coords: step.x.to_vec(),
+                        ..start.glyphs.clone()
+                    },
+                    steps: i,
+                    fx: step.fx,
+                    failures: 0,
+                    state: Some((step.state)()),
+                });
+            }
+            done
+        };
+        match start.state.clone() {
+            Some(state) => optimizer.resume(&mut rosenbrock, bounds, &mut x, state, stop),
+            None => optimizer.minimize(&mut rosenbrock, bounds, &mut x, stop),
+        }
+        end.unwrap()
+    }
+
+    #[test]
+    fn resume() {
+        let (lower, upper) = ([-2.; 4], [0.5, 2., 0.5, 2.]);
+        let start = Checkpoint::new(Glyphs {
+            indices: vec![0],
+            hues: vec![0.],
+            coords: vec![-1.2, 1., 0.3, -0.5],
+        });
+        let path = temp_dir().join(format!("checkpoint-{}.txt", std::process::id()));
+        for name in ["lbfgs", "momentum", "adam", "cg"] {
+            for bounds in [None, Some((&lower[..], &upper[..]))] {
+                let whole = run(name, bounds, start.clone(), 40);
+                run(name, bounds, start.clone(), 15)
+                    .save(&path, "a")
+                    .unwrap();
+                let resumed = run(name, bounds, Checkpoint::load(&path, "a").unwrap(), 40);
+                let bits = |c: &Checkpoint| {
+                    let coords = c.glyphs.coords.iter().map(|v| v.to_bits());
+                    coords.chain([c.fx.to_bits()]).collect::<Vec<_>>()
+                };
+                assert_eq!(
+                    bits(&whole),
+                    bits(&resumed),
+                    "{name}, bounded: {}",
+                    bounds.is_some()
+                );
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
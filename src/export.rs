@@ -0,0 +1,216 @@
+//! Writing arrangements out as data, for use elsewhere, and reading them back in.
+//!
+//! The JSON file has the run's details along with a list of the glyphs. The CSV file has only the
+//! glyphs, one per row, under a header naming the columns. Either one can be read back, to render
+//! the arrangement again or to start another optimization from it.
+
+use crate::{Glyphs, Shapes};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    fs::{read_to_string, File},
+    io::Write as _,
+    path::Path,
+};
+
+/// A finished arrangement and how it came about.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Export {
+    /// The seed for the starting arrangement, if it was random.
+    pub seed: Option<u64>,
+
+    /// How many glyphs there are.
+    pub n: usize,
+
+    /// The final objective value.
+    pub fx: f64,
+
+    /// How many steps the optimizer took.
+    pub iterations: usize,
+
+    pub glyphs: Vec<Placed>,
+}
+
+/// One glyph as it's drawn.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Placed {
+    pub letter: char,
+
+    /// Which of the distinct letters it is, counting from zero in the order they were given.
+    pub index: usize,
+
+    /// The hue of its fill, in degrees.
+    pub hue: f64,
+
+    /// Where its origin is in the container.
+    pub x: f64,
+    pub y: f64,
+
+    /// How far it's rotated clockwise, in degrees.
+    pub angle: f64,
+
+    /// How much its outline is scaled from the font's units.
+    pub scale: f64,
+}
+
+/// The columns of a CSV export.
+const HEADER: &str = "letter,index,hue,x,y,angle,scale";
+
+/// Return how each of `glyphs` is drawn, with their angles and scales clamped to what `shapes`
+/// allows just as they are when rendered.
+pub fn placed(shapes: &Shapes, glyphs: &Glyphs) -> Vec<Placed> {
+    let Glyphs {
+        indices,
+        hues,
+        coords,
+    } = glyphs;
+    let n = indices.len();
+    let layout = &shapes.layout;
+    (0..n)
+        .map(|i| Placed {
+            letter: shapes.glyphs[indices[i]].0,
+            index: indices[i],
+            hue: hues[i],
+            x: coords[i],
+            y: coords[n + i],
+            angle: layout.tilt(coords[2 * n + i]).to_degrees(),
+            scale: layout.scale * layout.grow(coords[3 * n + i]).exp(),
+        })
+        .collect()
+}
+
+/// Return the glyphs that `placed` describes, which are looked up by letter among those in
+/// `shapes`, so that the indices don't have to agree with the order they're given in. Fails if a
+/// letter isn't among them, a number isn't finite, or a scale isn't positive.
+pub fn glyphs(shapes: &Shapes, placed: &[Placed]) -> Result<Glyphs, String> {
+    let n = placed.len();
+    let mut glyphs = Glyphs {
+        indices: Vec::with_capacity(n),
+        hues: Vec::with_capacity(n),
+        coords: vec![0.; 4 * n],
+    };
+    for (i, p) in placed.iter().enumerate() {
+        let index = shapes
+            .glyphs
+            .iter()
+            .position(|&(c, _)| c == p.letter)
+            .ok_or_else(|| format!("{:?} isn't one of the letters", p.letter))?;
+        if ![p.hue, p.x, p.y, p.angle, p.scale]
+            .iter()
+            .all(|v| v.is_finite())
+        {
+            return Err(format!(
+                "glyph {i} ({:?}) should have finite numbers",
+                p.letter
+            ));
+        }
+        if p.scale <= 0. {
+            return Err(format!(
+                "glyph {i} ({:?}) should have a positive scale, not {}",
+                p.letter, p.scale
+            ));
+        }
+        glyphs.indices.push(index);
+        glyphs.hues.push(p.hue);
+        glyphs.coords[i] = p.x;
+        glyphs.coords[n + i] = p.y;
+        glyphs.coords[2 * n + i] = p.angle.to_radians();
+        glyphs.coords[3 * n + i] = (p.scale / shapes.layout.scale).ln();
+    }
+    Ok(glyphs)
+}
+
+impl Export {
+    /// Write `{name}.json` and `{name}.csv` into `dir`.
+    pub fn save(&self, dir: &Path, name: &str) {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        File::create(dir.join(format!("{name}.json")))
+            .unwrap()
+            .write_all(json.as_bytes())
+            .unwrap();
+        let mut csv = format!("{HEADER}\n");
+        for p in &self.glyphs {
+            let letter = match p.letter {
+                '"' => "\"\"\"\"".to_owned(),
+                ',' | '\n' | '\r' => format!("\"{}\"", p.letter),
+                c => c.to_string(),
+            };
+            let Placed {
+                index,
+                hue,
+                x,
+                y,
+                angle,
+                scale,
+                ..
+            } = p;
+            writeln!(csv, "{letter},{index},{hue},{x},{y},{angle},{scale}").unwrap();
+        }
+        File::create(dir.join(format!("{name}.csv")))
+            .unwrap()
+            .write_all(csv.as_bytes())
+            .unwrap();
+    }
+}
+
+/// Read the glyphs from a file written by `Export::save`, as JSON or CSV according to its
+/// extension.
+pub fn load(path: &Path) -> Result<Vec<Placed>, String> {
+    let text = read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let result = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&text)
+            .map(|export: Export| export.glyphs)
+            .map_err(|e| e.to_string()),
+        Some("csv") => parse_csv(&text),
+        _ => Err("expected a .json or .csv file".to_owned()),
+    };
+    result.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Parse the rows of a CSV export.
+fn parse_csv(text: &str) -> Result<Vec<Placed>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, HEADER)) => {}
+        _ => return Err(format!("expected the header {HEADER:?}")),
+    }
+    lines
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            let row = || format!("line {}", i + 1);
+            // the letter is the only column that might be quoted
+            let (letter, rest) = match line.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.rfind("\",").ok_or_else(row)?;
+                    (quoted[..end].replace("\"\"", "\""), &quoted[end + 2..])
+                }
+                None => {
+                    let (letter, rest) = line.split_once(',').ok_or_else(row)?;
+                    (letter.to_owned(), rest)
+                }
+            };
+            let mut chars = letter.chars();
+            let (Some(letter), None) = (chars.next(), chars.next()) else {
+                return Err(format!("{}: expected one letter, not {letter:?}", row()));
+            };
+            let values: Vec<&str> = rest.split(',').collect();
+            let [index, hue, x, y, angle, scale] = values[..] else {
+                return Err(format!("{}: expected 7 columns", row()));
+            };
+            let number = |s: &str| s.parse().map_err(|e| format!("{}: {s:?}: {e}", row()));
+            Ok(Placed {
+                letter,
+                index: index
+                    .parse()
+                    .map_err(|e| format!("{}: {index:?}: {e}", row()))?,
+                hue: number(hue)?,
+                x: number(x)?,
+                y: number(y)?,
+                angle: number(angle)?,
+                scale: number(scale)?,
+            })
+        })
+        .collect()
+}
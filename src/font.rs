@@ -0,0 +1,90 @@
+//! Extract glyph outlines from a TrueType or OpenType font as SVG path data.
+//!
+//! Font units have the y-axis pointing up, while SVG has it pointing down, so every outline is
+//! flipped vertically. Each glyph is also translated so that its bounding box starts at the origin,
+//! matching the convention that the optimizer uses for glyph coordinates.
+
+use crate::svg::PathData;
+use std::{fs, path::Path};
+use ttf_parser::{Face, OutlineBuilder};
+
+/// Accumulates SVG path data for one glyph, mapping font units to SVG units along the way.
+struct Svg {
+    /// SVG units per font unit.
+    scale: f64,
+
+    /// The font-unit x-coordinate that maps to zero.
+    x_min: f64,
+
+    /// The font-unit y-coordinate that maps to zero.
+    y_max: f64,
+
+    data: PathData,
+}
+
+impl Svg {
+    fn point(&self, x: f32, y: f32) -> (f64, f64) {
+        (
+            self.scale * (x as f64 - self.x_min),
+            self.scale * (self.y_max - y as f64),
+        )
+    }
+}
+
+impl OutlineBuilder for Svg {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.data.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.data.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x, y) = self.point(x, y);
+        self.data.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x2, y2) = self.point(x2, y2);
+        let (x, y) = self.point(x, y);
+        self.data.curve_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.data.close();
+    }
+}
+
+/// Return SVG path data for each of the `chars` in the font file at `path`.
+///
+/// Outlines are scaled so that one em is `size` SVG units. Fails if the file can't be read or
+/// parsed, or if the font has no outline for one of the characters.
+pub fn outlines(path: &Path, chars: &str, size: f64) -> Result<Vec<(char, String)>, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let face = Face::parse(&data, 0).map_err(|e| format!("{}: {e}", path.display()))?;
+    let scale = size / face.units_per_em() as f64;
+    chars
+        .chars()
+        .map(|c| {
+            let id = face
+                .glyph_index(c)
+                .ok_or_else(|| format!("{}: no glyph for {c:?}", path.display()))?;
+            let bbox = face
+                .glyph_bounding_box(id)
+                .ok_or_else(|| format!("{}: no outline for {c:?}", path.display()))?;
+            let mut svg = Svg {
+                scale,
+                x_min: bbox.x_min as f64,
+                y_max: bbox.y_max as f64,
+                data: PathData::default(),
+            };
+            face.outline_glyph(id, &mut svg);
+            Ok((c, svg.data.finish()))
+        })
+        .collect()
+}
@@ -28,11 +28,17 @@
 //! - _Engineering Optimization_ by Rao, 2009 edition
 //! - _Numerical Optimization_ by Nocedal and Wright, 1999 edition
 //!
+//! The bound-constrained variant, L-BFGS-B, follows _A Limited Memory Algorithm for Bound
+//! Constrained Optimization_ by Byrd, Lu, Nocedal and Zhu, 1995.
+//!
 //! A few functions in this module take a parameter of type `impl FnMut(&[f64], &mut [f64]) -> f64`.
 //! The first parameter is the point at which to evaluate the objective function, and the second
 //! parameter is an output parameter to hold the gradient at that point. The function should store
 //! the gradient in that output parameter and then return the objective value.
 
+use serde::Deserialize;
+use std::fmt;
+
 /// Configuration options for L-BFGS.
 #[derive(Clone, Copy, Debug)]
 pub struct Config {
@@ -51,10 +57,81 @@ pub struct Config {
     /// The maximum number of steps for line search.
     pub max_steps: usize,
 
+    /// Which line search to use.
+    pub search: Search,
+
+    /// What to do with pairs of too little curvature.
+    pub curvature: Curvature,
+
     /// A small positive constant to add to a denominator that might be zero.
     pub epsd: f64,
 }
 
+/// A way to choose the step size along a descent direction.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Search {
+    /// Double the step size until the Armijo condition fails, then bisect until the weak Wolfe
+    /// condition holds as well. Succeeds only if both hold within `max_steps` steps.
+    Bisection,
+
+    /// Find a step size satisfying the strong Wolfe conditions by safeguarded cubic and quadratic
+    /// interpolation, following _Line Search Algorithms with Guaranteed Sufficient Decrease_ by
+    /// Moré and Thuente, 1994. The minimum interval is relative to the step size, and `max_steps`
+    /// limits the number of evaluations.
+    MoreThuente,
+}
+
+/// What to do with an `(s, y)` pair whose curvature `s·y` is too small for the Hessian
+/// approximation to stay positive definite, as happens when the objective isn't convex along a
+/// step, or isn't smooth.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curvature {
+    /// Leave the pair out of the history if `s·y` is at most `epsd` times `y·y`.
+    Skip,
+
+    /// Instead of leaving out the pairs that `Skip` would, mix `y` with the initial Hessian
+    /// approximation times `s` until `s·y` is a fifth of the curvature that approximation predicts.
+    Damp,
+}
+
+/// How many times each kind of trouble has come up since the first step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counts {
+    /// Pairs with curvature `s·y` that isn't positive.
+    pub negative: usize,
+
+    /// Pairs left out of the history.
+    pub skipped: usize,
+
+    /// Pairs damped before going into the history.
+    pub damped: usize,
+
+    /// Directions that turned out not to be descent directions, so the history was reset.
+    pub ascents: usize,
+
+    /// Line searches that failed, so the history was reset.
+    pub failures: usize,
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Counts {
+            negative,
+            skipped,
+            damped,
+            ascents,
+            failures,
+        } = self;
+        write!(
+            f,
+            "{negative} pairs of negative curvature, {skipped} skipped, {damped} damped, \
+            {ascents} resets for ascent directions, {failures} for failed line searches"
+        )
+    }
+}
+
 /// All L-BFGS state that needs to be kept between iterations, other than the current point.
 #[derive(Clone, Debug)]
 pub struct State {
@@ -66,29 +143,71 @@ pub struct State {
 
     /// See page 224 of Nocedal and Wright.
     pub s_y: Vec<(Vec<f64>, Vec<f64>)>,
+
+    /// The scaling of the initial inverse Hessian approximation, kept across resets.
+    pub gamma: f64,
+
+    /// Whether the last line search failed, so the history should be forgotten.
+    pub reset: bool,
+
+    /// How many times each kind of trouble has come up so far.
+    pub counts: Counts,
 }
 
 /// Return the dot product of `u` and `v`.
-fn dot(u: &[f64], v: &[f64]) -> f64 {
+pub fn dot(u: &[f64], v: &[f64]) -> f64 {
     u.iter().zip(v).map(|(a, b)| a * b).sum()
 }
 
-/// Return the line search step size, having set `x` to the new point.
+/// Lower and upper bounds for each variable, either of which may be infinite.
+pub type Bounds<'a> = (&'a [f64], &'a [f64]);
+
+/// Clamp each variable in `x` to its bounds.
+pub fn project(x: &mut [f64], (lower, upper): Bounds) {
+    for i in 0..x.len() {
+        x[i] = x[i].clamp(lower[i], upper[i]);
+    }
+}
+
+/// Return the line search step size and whether it satisfies the conditions of `cfg.search`,
+/// having set `x` to the new point.
 ///
+/// - `bounds`, if any, are the bounds onto which each point tried is projected.
 /// - `x0` is the current point.
-/// - `r` is the descent direction, preconditioned by L-BFGS.
+/// - `r` is the descent direction, preconditioned by L-BFGS, and negated so that the points tried
+///   are `x0 - t * r`.
 /// - `fx0` is the objective at `x0`.
 /// - `grad` is the gradient at `x0`, and is then used as scratch space; don't depend on its value.
 /// - `x` will hold the new point.
-fn line_search(
+#[allow(clippy::too_many_arguments)]
+pub fn line_search(
+    cfg: Config,
+    f: impl FnMut(&[f64], &mut [f64]) -> f64,
+    bounds: Option<Bounds>,
+    x0: &[f64],
+    r: &[f64],
+    fx0: f64,
+    grad: &mut [f64],
+    x: &mut [f64],
+) -> (f64, bool) {
+    match cfg.search {
+        Search::Bisection => bisection(cfg, f, bounds, x0, r, fx0, grad, x),
+        Search::MoreThuente => more_thuente(cfg, f, bounds, x0, r, fx0, grad, x),
+    }
+}
+
+/// Perform `Search::Bisection`, taking the same arguments and returning the same as `line_search`.
+#[allow(clippy::too_many_arguments)]
+fn bisection(
     cfg: Config,
     mut f: impl FnMut(&[f64], &mut [f64]) -> f64,
+    bounds: Option<Bounds>,
     x0: &[f64],
     r: &[f64],
     fx0: f64,
     grad: &mut [f64],
     x: &mut [f64],
-) -> f64 {
+) -> (f64, bool) {
     let n = x.len();
 
     let duf_at_x0 = -dot(r, grad);
@@ -97,11 +216,15 @@ fn line_search(
     let mut b = f64::INFINITY;
     let mut t = 1.;
     let mut j = 0;
+    let mut found = false;
 
     loop {
         for i in 0..n {
             x[i] = x0[i] - t * r[i];
         }
+        if let Some(bounds) = bounds {
+            project(x, bounds);
+        }
 
         if (a - b).abs() < cfg.min_interval || j > cfg.max_steps {
             break;
@@ -116,6 +239,7 @@ fn line_search(
         } else if !is_wolfe {
             a = t;
         } else {
+            found = true;
             break; // found good interval
         }
 
@@ -128,7 +252,249 @@ fn line_search(
         j += 1;
     }
 
-    t
+    (t, found)
+}
+
+/// One end of the interval in `more_thuente`: a step size, with the objective and its derivative
+/// along the search direction there.
+#[derive(Clone, Copy)]
+struct End {
+    t: f64,
+    f: f64,
+    d: f64,
+}
+
+/// Return a safeguarded step size from `x` toward `y` given `t`, updating the interval `[x, y]`
+/// and whether it is known to bracket a minimizer. This is `dcstep` from MINPACK-2.
+fn more_thuente_step(
+    x: &mut End,
+    y: &mut End,
+    t: End,
+    brackt: &mut bool,
+    t_min: f64,
+    t_max: f64,
+) -> f64 {
+    let sgnd = t.d * x.d.signum();
+    let theta = 3. * (x.f - t.f) / (t.t - x.t) + x.d + t.d;
+    let s = theta.abs().max(x.d.abs()).max(t.d.abs());
+    let next = if t.f > x.f {
+        // higher objective, so the minimizer is bracketed
+        let mut gamma = s * ((theta / s).powi(2) - (x.d / s) * (t.d / s)).sqrt();
+        if t.t < x.t {
+            gamma = -gamma;
+        }
+        let p = (gamma - x.d) + theta;
+        let q = ((gamma - x.d) + gamma) + t.d;
+        let cubic = x.t + p / q * (t.t - x.t);
+        let quadratic = x.t + x.d / ((x.f - t.f) / (t.t - x.t) + x.d) / 2. * (t.t - x.t);
+        *brackt = true;
+        if (cubic - x.t).abs() < (quadratic - x.t).abs() {
+            cubic
+        } else {
+            cubic + (quadratic - cubic) / 2.
+        }
+    } else if sgnd < 0. {
+        // the derivatives have opposite signs, so the minimizer is bracketed
+        let mut gamma = s * ((theta / s).powi(2) - (x.d / s) * (t.d / s)).sqrt();
+        if t.t > x.t {
+            gamma = -gamma;
+        }
+        let p = (gamma - t.d) + theta;
+        let q = ((gamma - t.d) + gamma) + x.d;
+        let cubic = t.t + p / q * (x.t - t.t);
+        let secant = t.t + t.d / (t.d - x.d) * (x.t - t.t);
+        *brackt = true;
+        if (cubic - t.t).abs() > (secant - t.t).abs() {
+            cubic
+        } else {
+            secant
+        }
+    } else if t.d.abs() < x.d.abs() {
+        // the derivative is decreasing in magnitude
+        let mut gamma = s * ((theta / s).powi(2) - (x.d / s) * (t.d / s)).max(0.).sqrt();
+        if t.t > x.t {
+            gamma = -gamma;
+        }
+        let p = (gamma - t.d) + theta;
+        let q = (gamma + (x.d - t.d)) + gamma;
+        let r = p / q;
+        let cubic = if r < 0. && gamma != 0. {
+            t.t + r * (x.t - t.t)
+        } else if t.t > x.t {
+            t_max
+        } else {
+            t_min
+        };
+        let secant = t.t + t.d / (t.d - x.d) * (x.t - t.t);
+        if *brackt {
+            let next = if (cubic - t.t).abs() < (secant - t.t).abs() {
+                cubic
+            } else {
+                secant
+            };
+            if t.t > x.t {
+                next.min(t.t + 0.66 * (y.t - t.t))
+            } else {
+                next.max(t.t + 0.66 * (y.t - t.t))
+            }
+        } else {
+            let next = if (cubic - t.t).abs() > (secant - t.t).abs() {
+                cubic
+            } else {
+                secant
+            };
+            next.clamp(t_min, t_max)
+        }
+    } else if *brackt {
+        // the derivative isn't decreasing in magnitude, so minimize the cubic through `t` and `y`
+        let theta = 3. * (t.f - y.f) / (y.t - t.t) + y.d + t.d;
+        let s = theta.abs().max(y.d.abs()).max(t.d.abs());
+        let mut gamma = s * ((theta / s).powi(2) - (y.d / s) * (t.d / s)).sqrt();
+        if t.t > y.t {
+            gamma = -gamma;
+        }
+        let p = (gamma - t.d) + theta;
+        let q = ((gamma - t.d) + gamma) + y.d;
+        t.t + p / q * (y.t - t.t)
+    } else if t.t > x.t {
+        t_max
+    } else {
+        t_min
+    };
+    if t.f > x.f {
+        *y = t;
+    } else {
+        if sgnd < 0. {
+            *y = *x;
+        }
+        *x = t;
+    }
+    next
+}
+
+/// Perform `Search::MoreThuente`, taking the same arguments and returning the same as
+/// `line_search`. If it fails, `x` is the best point it found.
+#[allow(clippy::too_many_arguments)]
+fn more_thuente(
+    cfg: Config,
+    mut f: impl FnMut(&[f64], &mut [f64]) -> f64,
+    bounds: Option<Bounds>,
+    x0: &[f64],
+    r: &[f64],
+    fx0: f64,
+    grad: &mut [f64],
+    x: &mut [f64],
+) -> (f64, bool) {
+    let n = x.len();
+    let (t_min, t_max) = (0., 1e20);
+
+    // the derivative along the search direction, ignoring variables held at a bound
+    let slope = |x: &[f64], t: f64, grad: &[f64]| -> f64 {
+        -(0..n)
+            .filter(|&i| bounds.is_none() || x[i] == x0[i] - t * r[i])
+            .map(|i| r[i] * grad[i])
+            .sum::<f64>()
+    };
+    let d0 = slope(x0, 0., grad);
+    if d0.is_nan() || d0 >= 0. {
+        // not a descent direction, so there's no step to take
+        x.copy_from_slice(x0);
+        return (0., false);
+    }
+    let test = cfg.armijo * d0;
+    let start = End {
+        t: 0.,
+        f: fx0,
+        d: d0,
+    };
+    let (mut lo, mut hi) = (start, start);
+    let mut brackt = false;
+    let mut stage1 = true;
+    let mut width = t_max - t_min;
+    let mut width1 = 2. * width;
+    let mut t = 1.;
+    let (mut lower, mut upper) = (0., t + 4. * t);
+    let mut found = false;
+
+    for _ in 0..cfg.max_steps.max(1) {
+        for i in 0..n {
+            x[i] = x0[i] - t * r[i];
+        }
+        if let Some(bounds) = bounds {
+            project(x, bounds);
+        }
+        let fx = f(x, grad);
+        if !fx.is_finite() {
+            // too far to say anything useful, so back off toward the best step so far
+            upper = t;
+            t = lo.t + (t - lo.t) / 2.;
+            continue;
+        }
+        let dx = slope(x, t, grad);
+        let sufficient = fx <= fx0 + t * test;
+        if stage1 && sufficient && dx >= 0. {
+            stage1 = false;
+        }
+        if sufficient && dx.abs() <= cfg.wolfe * -d0 {
+            found = true;
+            break;
+        }
+        if brackt && (t <= lower || t >= upper || upper - lower <= cfg.min_interval * upper)
+            || t == t_max && sufficient && dx <= test
+            || t == t_min && (!sufficient || dx >= test)
+        {
+            break;
+        }
+        let here = End { t, f: fx, d: dx };
+        t = if stage1 && fx <= lo.f && !sufficient {
+            // use the modified function, which has zero derivative where the Armijo condition is
+            // exactly met, until a step satisfies it with a nonnegative derivative
+            let modify = |e: End| End {
+                t: e.t,
+                f: e.f - e.t * test,
+                d: e.d - test,
+            };
+            let unmodify = |e: End| End {
+                t: e.t,
+                f: e.f + e.t * test,
+                d: e.d + test,
+            };
+            let (mut mlo, mut mhi) = (modify(lo), modify(hi));
+            let next =
+                more_thuente_step(&mut mlo, &mut mhi, modify(here), &mut brackt, lower, upper);
+            (lo, hi) = (unmodify(mlo), unmodify(mhi));
+            next
+        } else {
+            more_thuente_step(&mut lo, &mut hi, here, &mut brackt, lower, upper)
+        };
+        if brackt {
+            if (hi.t - lo.t).abs() >= 0.66 * width1 {
+                t = lo.t + 0.5 * (hi.t - lo.t);
+            }
+            width1 = width;
+            width = (hi.t - lo.t).abs();
+            (lower, upper) = (lo.t.min(hi.t), lo.t.max(hi.t));
+        } else {
+            (lower, upper) = (t + 1.1 * (t - lo.t), t + 4. * (t - lo.t));
+        }
+        t = t.clamp(t_min, t_max);
+        if brackt && (t <= lower || t >= upper || upper - lower <= cfg.min_interval * upper) {
+            // rounding errors prevent progress, so settle for the best step so far
+            t = lo.t;
+        }
+    }
+
+    if !found {
+        // the best step found so far is the low end of the interval
+        t = lo.t;
+        for i in 0..n {
+            x[i] = x0[i] - t * r[i];
+        }
+        if let Some(bounds) = bounds {
+            project(x, bounds);
+        }
+    }
+    (t, found)
 }
 
 /// Perform the first step of L-BFGS at point `x`, updating it and returning the initial `State`.
@@ -144,12 +510,15 @@ pub fn first_step(
     let fx = f(x, &mut grad);
 
     let r = grad.clone();
-    line_search(cfg, f, &x0, &r, fx, &mut grad, x);
+    line_search(cfg, f, None, &x0, &r, fx, &mut grad, x);
 
     State {
         x: x0,
         grad: r,
         s_y: vec![],
+        gamma: 1.,
+        reset: false,
+        counts: Counts::default(),
     }
 }
 
@@ -162,14 +531,41 @@ pub struct Info<'a> {
     /// The objective value at the current point.
     pub fx: f64,
 
-    /// The preconditioned descent direction.
-    pub r: &'a [f64],
-
     /// The current point.
     pub x: &'a [f64],
 
-    /// The line search step size.
-    pub t: f64,
+    /// Whether the line search found a step size satisfying its conditions. If not, the next step
+    /// starts over from steepest descent.
+    pub found: bool,
+}
+
+/// Put the pair `(s, y)` at the front of `state.s_y` if `cfg.curvature` allows, possibly modified.
+fn remember(cfg: Config, state: &mut State, s: Vec<f64>, mut y: Vec<f64>) {
+    let (gamma, counts) = (state.gamma, &mut state.counts);
+    let sy = dot(&s, &y);
+    if sy <= 0. {
+        counts.negative += 1;
+    }
+    if sy <= cfg.epsd * dot(&y, &y) {
+        // the initial Hessian approximation is the identity divided by `gamma`
+        let sbs = dot(&s, &s) / gamma;
+        match cfg.curvature {
+            Curvature::Damp if sbs > 0. => {
+                let theta = 0.8 * sbs / (sbs - sy);
+                for i in 0..y.len() {
+                    y[i] = theta * y[i] + (1. - theta) * s[i] / gamma;
+                }
+                counts.damped += 1;
+            }
+            // with no step, there's nothing to learn from it anyway
+            _ => {
+                counts.skipped += 1;
+                return;
+            }
+        }
+    }
+    state.s_y.truncate(cfg.m - 1);
+    state.s_y.insert(0, (s, y));
 }
 
 /// Perform L-BFGS steps on `x`, returning once `stop` returns `Some`.
@@ -191,19 +587,14 @@ pub fn step_until<T>(
     loop {
         let fx = f(x, &mut grad);
 
-        if state.s_y.len() < cfg.m {
-            state.s_y.push((
-                x.iter().zip(&state.x).map(|(a, b)| a - b).collect(),
-                grad.iter().zip(&state.grad).map(|(a, b)| a - b).collect(),
-            ));
+        if state.reset {
+            // the last step may have been a bad one, so forget it along with the rest
+            state.s_y.clear();
         } else {
-            let (s, y) = state.s_y.last_mut().expect("m > 0");
-            for i in 0..n {
-                s[i] = x[i] - state.x[i];
-                y[i] = grad[i] - state.grad[i];
-            }
+            let s = x.iter().zip(&state.x).map(|(a, b)| a - b).collect();
+            let y = grad.iter().zip(&state.grad).map(|(a, b)| a - b).collect();
+            remember(cfg, state, s, y);
         }
-        state.s_y.rotate_right(1);
 
         state.x.copy_from_slice(x);
         state.grad.copy_from_slice(&grad);
@@ -223,10 +614,12 @@ pub fn step_until<T>(
         }
 
         // see page 226 of Nocedal and Wright
-        let (s_k, y_k) = &state.s_y[0];
-        let gamma = dot(s_k, y_k) / (dot(y_k, y_k) + cfg.epsd);
+        // after a reset, keep the scaling from before it
+        if let Some((s_k, y_k)) = state.s_y.first() {
+            state.gamma = dot(s_k, y_k) / (dot(y_k, y_k) + cfg.epsd);
+        }
         for i in 0..n {
-            r[i] = gamma * q[i];
+            r[i] = state.gamma * q[i];
         }
 
         for (j, (s_j, y_j)) in state.s_y.iter().enumerate().rev() {
@@ -237,16 +630,521 @@ pub fn step_until<T>(
             }
         }
 
-        let t = line_search(cfg, &mut f, &state.x, &r, fx, &mut grad, x);
+        let slope = dot(&r, &grad);
+        if (slope.is_nan() || slope <= 0.) && grad.iter().any(|&g| g != 0.) {
+            // the history no longer describes the objective here, so start over
+            state.counts.ascents += 1;
+            state.s_y.clear();
+            for i in 0..n {
+                r[i] = state.gamma * grad[i];
+            }
+        }
+
+        let (_, found) = line_search(cfg, &mut f, None, &state.x, &r, fx, &mut grad, x);
+        state.reset = !found;
+        if !found {
+            state.counts.failures += 1;
+        }
+
+        if let Some(msg) = stop(Info {
+            state,
+            fx,
+            x,
+            found,
+        }) {
+            return msg;
+        }
+    }
+}
+
+/// Return the product of the small matrix `a`, given as rows, and the vector `v`.
+fn mul(a: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    a.iter().map(|row| dot(row, v)).collect()
+}
+
+/// Return the inverse of the small square matrix `a`, given as rows, by Gauss-Jordan elimination,
+/// or `None` if it's singular, or too close to it to invert in floating point.
+fn inverse(mut a: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1. } else { 0. }).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let d = a[col][col];
+        if d == 0. || !d.is_finite() {
+            return None;
+        }
+        for j in 0..n {
+            a[col][j] /= d;
+            inv[col][j] /= d;
+        }
+        let (row, inv_row) = (a[col].clone(), inv[col].clone());
+        for i in 0..n {
+            let factor = a[i][col];
+            if i != col && factor != 0. {
+                for j in 0..n {
+                    a[i][j] -= factor * row[j];
+                    inv[i][j] -= factor * inv_row[j];
+                }
+            }
+        }
+    }
+    inv.iter().flatten().all(|x| x.is_finite()).then_some(inv)
+}
+
+/// Return the point L-BFGS-B steps toward from `x`, with gradient `grad`, given the pairs in
+/// `s_y`, most recent first, or the scaling `gamma` of the initial inverse Hessian approximation if
+/// there are none. See sections 3 to 5 of Byrd et al.
+fn bounded_target(
+    x: &[f64],
+    grad: &[f64],
+    s_y: &[(Vec<f64>, Vec<f64>)],
+    gamma: f64,
+    bounds: Bounds,
+) -> Vec<f64> {
+    let (lower, upper) = bounds;
+    let n = x.len();
+    let k = s_y.len();
+
+    // the compact representation B = θI - WMWᵀ of the L-BFGS Hessian approximation, where the
+    // columns of W are the y's and then θ times the s's, from oldest to newest
+    let theta = match s_y.first() {
+        Some((s, y)) => dot(y, y) / dot(s, y),
+        None => 1. / gamma,
+    };
+    let (s, y): (Vec<&[f64]>, Vec<&[f64]>) =
+        s_y.iter().rev().map(|(s, y)| (&s[..], &y[..])).unzip();
+    let w: Vec<Vec<f64>> = y
+        .iter()
+        .map(|y| y.to_vec())
+        .chain(s.iter().map(|s| s.iter().map(|s| theta * s).collect()))
+        .collect();
+    let mut k_mat = vec![vec![0.; 2 * k]; 2 * k];
+    for i in 0..k {
+        k_mat[i][i] = -dot(s[i], y[i]);
+        for j in 0..k {
+            if i > j {
+                k_mat[k + i][j] = dot(s[i], y[j]);
+                k_mat[j][k + i] = k_mat[k + i][j];
+            }
+            k_mat[k + i][k + j] = theta * dot(s[i], s[j]);
+        }
+    }
+    let Some(m) = inverse(k_mat) else {
+        // the pairs are too nearly dependent to describe the Hessian, so do without them
+        return bounded_target(x, grad, &[], gamma, bounds);
+    };
+    let row = |i: usize| -> Vec<f64> { w.iter().map(|col| col[i]).collect() };
+
+    // the generalized Cauchy point: the first local minimizer of the quadratic model along the
+    // projected steepest descent path
+    let breaks: Vec<f64> = (0..n)
+        .map(|i| {
+            if grad[i] < 0. {
+                (x[i] - upper[i]) / grad[i]
+            } else if grad[i] > 0. {
+                (x[i] - lower[i]) / grad[i]
+            } else {
+                f64::INFINITY
+            }
+        })
+        .collect();
+    let mut d: Vec<f64> = (0..n)
+        .map(|i| if breaks[i] > 0. { -grad[i] } else { 0. })
+        .collect();
+    let mut order: Vec<usize> = (0..n).filter(|&i| breaks[i] > 0.).collect();
+    order.sort_by(|&i, &j| breaks[i].total_cmp(&breaks[j]));
+    let mut xcp = x.to_vec();
+    let mut p: Vec<f64> = w.iter().map(|col| dot(col, &d)).collect();
+    let mut c = vec![0.; 2 * k];
+    let mut fp = -dot(&d, &d);
+    if fp == 0. {
+        // every variable is stuck at a bound
+        return xcp;
+    }
+    let mut fpp = -theta * fp - dot(&p, &mul(&m, &p));
+    let mut dt_min = -fp / fpp;
+    let mut t_old = 0.;
+    for &b in &order {
+        let dt = breaks[b] - t_old;
+        if dt_min < dt {
+            break;
+        }
+        xcp[b] = if d[b] > 0. { upper[b] } else { lower[b] };
+        let zb = xcp[b] - x[b];
+        for j in 0..2 * k {
+            c[j] += dt * p[j];
+        }
+        let gb = grad[b];
+        let wb = row(b);
+        let mwb = mul(&m, &wb);
+        fp += dt * fpp + gb * gb + theta * gb * zb - gb * dot(&mwb, &c);
+        fpp -= theta * gb * gb + 2. * gb * dot(&mwb, &p) + gb * gb * dot(&mwb, &wb);
+        for j in 0..2 * k {
+            p[j] += gb * wb[j];
+        }
+        d[b] = 0.;
+        dt_min = -fp / fpp;
+        t_old = breaks[b];
+    }
+    let dt_min = dt_min.max(0.);
+    t_old += dt_min;
+    for i in 0..n {
+        if d[i] != 0. {
+            xcp[i] = x[i] + t_old * d[i];
+        }
+    }
+    for j in 0..2 * k {
+        c[j] += dt_min * p[j];
+    }
+
+    // minimize the quadratic model over the variables not at a bound, by the direct primal method,
+    // then go as far toward that minimizer as the bounds allow
+    let free: Vec<usize> = (0..n)
+        .filter(|&i| lower[i] < xcp[i] && xcp[i] < upper[i])
+        .collect();
+    let mc = mul(&m, &c);
+    let rc: Vec<f64> = free
+        .iter()
+        .map(|&i| grad[i] + theta * (xcp[i] - x[i]) - dot(&row(i), &mc))
+        .collect();
+    let v: Vec<f64> = w
+        .iter()
+        .map(|col| free.iter().zip(&rc).map(|(&i, r)| col[i] * r).sum())
+        .collect();
+    let v = mul(&m, &v);
+    let wtw: Vec<Vec<f64>> = w
+        .iter()
+        .map(|a| {
+            w.iter()
+                .map(|b| free.iter().map(|&i| a[i] * b[i]).sum())
+                .collect()
+        })
+        .collect();
+    let n_mat: Vec<Vec<f64>> = (0..2 * k)
+        .map(|a| {
+            (0..2 * k)
+                .map(|b| {
+                    let mwtw: f64 = (0..2 * k).map(|j| m[a][j] * wtw[j][b]).sum();
+                    (if a == b { 1. } else { 0. }) - mwtw / theta
+                })
+                .collect()
+        })
+        .collect();
+    let Some(n_inv) = inverse(n_mat) else {
+        // likewise, but the Cauchy point is still a step in the right direction
+        return xcp;
+    };
+    let v = mul(&n_inv, &v);
+    let du: Vec<f64> = free
+        .iter()
+        .zip(&rc)
+        .map(|(&i, r)| -r / theta - dot(&row(i), &v) / (theta * theta))
+        .collect();
+    let mut alpha: f64 = 1.;
+    for (&i, &du) in free.iter().zip(&du) {
+        if du > 0. {
+            alpha = alpha.min((upper[i] - xcp[i]) / du);
+        } else if du < 0. {
+            alpha = alpha.min((lower[i] - xcp[i]) / du);
+        }
+    }
+    let mut xbar = xcp;
+    for (&i, &du) in free.iter().zip(&du) {
+        xbar[i] += alpha * du;
+    }
+    xbar
+}
+
+/// Like `first_step`, but keeping each variable within `bounds`, which `x` is first projected
+/// onto.
+pub fn first_step_bounded(
+    cfg: Config,
+    mut f: impl FnMut(&[f64], &mut [f64]) -> f64,
+    bounds: Bounds,
+    x: &mut [f64],
+) -> State {
+    let n = x.len();
+    project(x, bounds);
+    let x0 = x.to_vec();
+
+    let mut grad = vec![0.; n];
+    let fx = f(x, &mut grad);
+
+    let r = grad.clone();
+    line_search(cfg, &mut f, Some(bounds), &x0, &r, fx, &mut grad, x);
+
+    State {
+        x: x0,
+        grad: r,
+        s_y: vec![],
+        gamma: 1.,
+        reset: false,
+        counts: Counts::default(),
+    }
+}
+
+/// Like `step_until`, but performing L-BFGS-B steps to keep each variable within `bounds`.
+pub fn step_until_bounded<T>(
+    cfg: Config,
+    mut f: impl FnMut(&[f64], &mut [f64]) -> f64,
+    bounds: Bounds,
+    x: &mut [f64],
+    state: &mut State,
+    mut stop: impl FnMut(Info) -> Option<T>,
+) -> T {
+    let n = x.len();
+    let mut grad = vec![0.; n];
+    let mut r = vec![0.; n];
+
+    loop {
+        let fx = f(x, &mut grad);
+
+        if state.reset {
+            state.s_y.clear();
+        } else {
+            let s = x.iter().zip(&state.x).map(|(a, b)| a - b).collect();
+            let y = grad.iter().zip(&state.grad).map(|(a, b)| a - b).collect();
+            remember(cfg, state, s, y);
+        }
+        if let Some((s, y)) = state.s_y.first() {
+            state.gamma = dot(s, y) / (dot(y, y) + cfg.epsd);
+        }
+
+        state.x.copy_from_slice(x);
+        state.grad.copy_from_slice(&grad);
+
+        let target = bounded_target(x, &grad, &state.s_y, state.gamma, bounds);
+        for i in 0..n {
+            r[i] = x[i] - target[i];
+        }
+        let slope = dot(&r, &grad);
+        if slope.is_nan() || slope < 0. {
+            // rounding has made this an ascent direction, so fall back to the projected gradient
+            state.counts.ascents += 1;
+            state.s_y.clear();
+            for i in 0..n {
+                r[i] = state.gamma * grad[i];
+            }
+        }
+
+        let (_, found) = line_search(cfg, &mut f, Some(bounds), &state.x, &r, fx, &mut grad, x);
+        state.reset = !found;
+        if !found {
+            state.counts.failures += 1;
+        }
 
         if let Some(msg) = stop(Info {
             state,
             fx,
-            r: &r,
-            t,
             x,
+            found,
         }) {
             return msg;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(search: Search) -> Config {
+        Config {
+            m: 17,
+            armijo: 0.001,
+            wolfe: 0.9,
+            min_interval: 1e-9,
+            max_steps: 10,
+            search,
+            curvature: Curvature::Damp,
+            epsd: 1e-11,
+        }
+    }
+
+    /// Take `steps` steps of L-BFGS-B from `x`.
+    fn minimize(
+        cfg: Config,
+        mut f: impl FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Bounds,
+        x: &mut [f64],
+        steps: usize,
+    ) {
+        let mut state = first_step_bounded(cfg, &mut f, bounds, x);
+        let mut i = 0;
+        step_until_bounded(cfg, &mut f, bounds, x, &mut state, |_| {
+            i += 1;
+            (i >= steps).then_some(())
+        });
+    }
+
+    fn assert_near(x: &[f64], expected: &[f64]) {
+        for (a, b) in x.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{x:?} should be {expected:?}");
+        }
+    }
+
+    #[test]
+    fn singular_inverse() {
+        let inv = inverse(vec![vec![2., 1.], vec![1., 1.]]);
+        assert_eq!(inv, Some(vec![vec![1., -1.], vec![-1., 2.]]));
+        assert_eq!(inverse(vec![vec![1., 2.], vec![2., 4.]]), None);
+    }
+
+    /// Return a state at `x` with gradient `grad` and no history.
+    fn state(x: &[f64], grad: &[f64], gamma: f64) -> State {
+        State {
+            x: x.to_vec(),
+            grad: grad.to_vec(),
+            s_y: vec![],
+            gamma,
+            reset: false,
+            counts: Counts::default(),
+        }
+    }
+
+    #[test]
+    fn curvature_skip() {
+        let cfg = Config {
+            curvature: Curvature::Skip,
+            ..config(Search::MoreThuente)
+        };
+        let mut state = state(&[0., 0.], &[0., 0.], 1.);
+        remember(cfg, &mut state, vec![1., 0.], vec![-1., 0.]);
+        assert!(state.s_y.is_empty());
+        remember(cfg, &mut state, vec![1., 0.], vec![2., 0.]);
+        assert_eq!(state.s_y, [(vec![1., 0.], vec![2., 0.])]);
+        let counts = state.counts;
+        assert_eq!((counts.negative, counts.skipped, counts.damped), (1, 1, 0));
+    }
+
+    #[test]
+    fn curvature_damp() {
+        let cfg = config(Search::MoreThuente);
+        let gamma = 0.5;
+        let mut state = state(&[0., 0.], &[0., 0.], gamma);
+        let s = vec![1., 2.];
+        remember(cfg, &mut state, s.clone(), vec![-1., 0.5]);
+        let [(_, y)] = &state.s_y[..] else {
+            panic!("the pair should have been kept");
+        };
+        let sbs = dot(&s, &s) / gamma;
+        assert!(
+            (dot(&s, y) - 0.2 * sbs).abs() < 1e-12,
+            "{y:?} is damped wrong"
+        );
+        let counts = state.counts;
+        assert_eq!((counts.negative, counts.skipped, counts.damped), (1, 0, 1));
+    }
+
+    #[test]
+    fn ascent_reset() {
+        let mut f = |x: &[f64], grad: &mut [f64]| {
+            grad.copy_from_slice(x);
+            dot(x, x) / 2.
+        };
+        let mut x = [1., 1.];
+        // `remember` never keeps the second pair, whose negative curvature makes the two-loop
+        // recursion point uphill
+        let mut state = state(&x, &x, 1.);
+        state.s_y = vec![(vec![1., 0.], vec![1., 0.]), (vec![0., 1.], vec![0., -0.5])];
+        let cfg = config(Search::MoreThuente);
+        let counts = step_until(cfg, &mut f, &mut x, &mut state, |info| {
+            assert!(info.found);
+            Some(info.state.counts)
+        });
+        assert!(state.s_y.is_empty());
+        assert_near(&x, &[0., 0.]);
+        let counts = (counts.ascents, counts.failures, counts.skipped);
+        assert_eq!(counts, (1, 0, 1));
+    }
+
+    #[test]
+    fn more_thuente_wolfe() {
+        // f(x) = x^4 / 4 - x has its minimum at 1, far past the first trial step
+        let mut f = |x: &[f64], grad: &mut [f64]| {
+            grad[0] = x[0].powi(3) - 1.;
+            x[0].powi(4) / 4. - x[0]
+        };
+        let cfg = Config {
+            wolfe: 0.1,
+            ..config(Search::MoreThuente)
+        };
+        let (x0, r) = ([0.], [-0.1]);
+        let mut grad = [0.];
+        let fx0 = f(&x0, &mut grad);
+        let d0 = -r[0] * grad[0];
+        let mut x = [0.];
+        let (t, found) = line_search(cfg, &mut f, None, &x0, &r, fx0, &mut grad, &mut x);
+        assert!(found);
+        assert_eq!(x[0], x0[0] - t * r[0]);
+        let fx = f(&x, &mut grad);
+        let d = -r[0] * grad[0];
+        assert!(
+            fx <= fx0 + cfg.armijo * t * d0,
+            "{fx} is not a sufficient decrease"
+        );
+        assert!(d.abs() <= cfg.wolfe * d0.abs(), "{d} is too steep");
+    }
+
+    #[test]
+    fn more_thuente_unbounded() {
+        // f(x) = -x keeps decreasing at the same rate, so no step meets the curvature condition
+        let mut f = |x: &[f64], grad: &mut [f64]| {
+            grad[0] = -1.;
+            -x[0]
+        };
+        let (x0, r) = ([0.], [-1.]);
+        let mut grad = [0.];
+        let fx0 = f(&x0, &mut grad);
+        let mut x = [0.];
+        let cfg = config(Search::MoreThuente);
+        let (_, found) = line_search(cfg, &mut f, None, &x0, &r, fx0, &mut grad, &mut x);
+        assert!(!found);
+    }
+
+    #[test]
+    fn bounded_quadratic() {
+        // each variable wants to go to `c`, which is below, inside, or above its bounds
+        let w = [1., 10., 100., 3.];
+        let c = [-1., 0.5, 2., 0.25];
+        let f = |x: &[f64], grad: &mut [f64]| {
+            let mut fx = 0.;
+            for i in 0..x.len() {
+                fx += w[i] * (x[i] - c[i]).powi(2);
+                grad[i] = 2. * w[i] * (x[i] - c[i]);
+            }
+            fx
+        };
+        let lower = [0., f64::NEG_INFINITY, 0., 0.];
+        let upper = [1., f64::INFINITY, 1., 1.];
+        for search in [Search::Bisection, Search::MoreThuente] {
+            let mut x = [0.5, 3., 0.5, 0.9];
+            minimize(config(search), f, (&lower, &upper), &mut x, 50);
+            assert_near(&x, &[0., 0.5, 1., 0.25]);
+        }
+    }
+
+    #[test]
+    fn bounded_rosenbrock() {
+        // the unconstrained minimum is at (1, 1), but with `a` at most 0.5, the best is to follow
+        // the valley to its edge
+        let f = |x: &[f64], grad: &mut [f64]| {
+            let (a, b) = (x[0], x[1]);
+            grad[0] = -2. * (1. - a) - 400. * a * (b - a * a);
+            grad[1] = 200. * (b - a * a);
+            (1. - a).powi(2) + 100. * (b - a * a).powi(2)
+        };
+        let (lower, upper) = ([-2., -2.], [0.5, 2.]);
+        for search in [Search::Bisection, Search::MoreThuente] {
+            let mut x = [-1.2, 1.];
+            minimize(config(search), f, (&lower, &upper), &mut x, 200);
+            assert_near(&x, &[0.5, 0.25]);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Choose which glyph each of the small glyphs is.
+//!
+//! Glyphs are identified by their index into `Letters::chars`, which is also the order in which
+//! their outlines are loaded from the font.
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use rand_pcg::Pcg64Mcg;
+use std::{iter, str::FromStr};
+
+/// Which letters to pack into the container.
+///
+/// These can be parsed from a string in one of these forms:
+///
+/// - `AGHIPRS`, where each glyph is drawn uniformly at random from these characters.
+/// - `S:3 P:1.5 R:1`, where each glyph is drawn at random, with each character weighted by the
+///   number alongside it.
+/// - `12S 10P 8R`, for exactly this many of each character.
+/// - `"HELLO WORLD"`, with the quotes, for the characters of this text in order, ignoring
+///   whitespace, so only positions are optimized.
+pub struct Letters {
+    /// Each distinct character, in order of first appearance.
+    chars: Vec<char>,
+
+    choice: Choice,
+}
+
+/// How each glyph is chosen from `Letters::chars`.
+enum Choice {
+    /// Uniformly at random.
+    Uniform,
+
+    /// At random, weighted according to the order of the characters.
+    Weighted(WeightedIndex<f64>),
+
+    /// In this order, as indices, which is how both counts and text are given.
+    Sequence(Vec<usize>),
+}
+
+impl Letters {
+    /// Return each distinct character, in order of first appearance.
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Return how many glyphs these letters call for, if they call for a particular number.
+    pub fn count(&self) -> Option<usize> {
+        match &self.choice {
+            Choice::Uniform | Choice::Weighted(_) => None,
+            Choice::Sequence(sequence) => Some(sequence.len()),
+        }
+    }
+
+    /// Return the index in `chars` of the glyph numbered `i`.
+    ///
+    /// Past `count`, the counts or the text start over from the beginning.
+    pub fn pick(&self, rng: &mut Pcg64Mcg, i: usize) -> usize {
+        match &self.choice {
+            Choice::Uniform => rng.gen_range(0..self.chars.len()),
+            Choice::Weighted(weights) => weights.sample(rng),
+            Choice::Sequence(sequence) => sequence[i % sequence.len()],
+        }
+    }
+}
+
+/// Return each distinct character of `all`, in order of first appearance, along with the index
+/// among those of each character of `all`.
+fn distinct(all: impl IntoIterator<Item = char>) -> (Vec<char>, Vec<usize>) {
+    let mut chars = vec![];
+    let indices = all
+        .into_iter()
+        .map(|c| match chars.iter().position(|&d| d == c) {
+            Some(i) => i,
+            None => {
+                chars.push(c);
+                chars.len() - 1
+            }
+        })
+        .collect();
+    (chars, indices)
+}
+
+impl FromStr for Letters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let letters = if let Some(text) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            let (chars, sequence) = distinct(text.chars().filter(|c| !c.is_whitespace()));
+            Letters {
+                chars,
+                choice: Choice::Sequence(sequence),
+            }
+        } else if s.contains(':') {
+            let tokens = s.split_whitespace().map(|token| {
+                let err = || format!("{token:?} should be a letter, a colon, and a weight");
+                let (c, w) = token.split_once(':').ok_or_else(err)?;
+                let mut chars = c.chars();
+                match (chars.next(), chars.next(), w.parse::<f64>()) {
+                    (Some(c), None, Ok(w)) if w >= 0. && w.is_finite() => Ok((c, w)),
+                    _ => Err(err()),
+                }
+            });
+            let tokens: Vec<(char, f64)> = tokens.collect::<Result<_, _>>()?;
+            let (chars, indices) = distinct(tokens.iter().map(|&(c, _)| c));
+            // a letter given more than once gets all of its weights
+            let mut weights = vec![0.; chars.len()];
+            for (&i, &(_, w)) in indices.iter().zip(&tokens) {
+                weights[i] += w;
+            }
+            let weights = WeightedIndex::new(weights)
+                .map_err(|_| "the weights should not all be zero".to_owned())?;
+            Letters {
+                chars,
+                choice: Choice::Weighted(weights),
+            }
+        } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+            let counts = s.split_whitespace().map(|token| {
+                let mut chars = token.chars();
+                match (chars.next_back(), chars.as_str().parse()) {
+                    (Some(c), Ok(k)) => Ok(iter::repeat_n(c, k)),
+                    _ => Err(format!("{token:?} should be a count followed by a letter")),
+                }
+            });
+            let counts: Vec<_> = counts.collect::<Result<_, _>>()?;
+            let (chars, sequence) = distinct(counts.into_iter().flatten());
+            Letters {
+                chars,
+                choice: Choice::Sequence(sequence),
+            }
+        } else {
+            let (chars, _) = distinct(s.chars().filter(|c| !c.is_whitespace()));
+            Letters {
+                chars,
+                choice: Choice::Uniform,
+            }
+        };
+        match letters.count() {
+            Some(0) => Err("there should be some glyphs".to_owned()),
+            _ if letters.chars.is_empty() => Err("there should be some letters".to_owned()),
+            _ => Ok(letters),
+        }
+    }
+}
@@ -1,8 +1,24 @@
+mod capture;
+mod checkpoint;
+mod export;
+mod font;
 mod lbfgs;
+mod letters;
+mod optimizer;
+mod scene;
+mod svg;
+mod verify;
 
+use capture::Capture;
+use checkpoint::Checkpoint;
+use clap::{ArgGroup, Parser, Subcommand};
+use export::Export;
+use letters::Letters;
 use minkowski::{extract_loops, reduced_convolution, Point};
+use optimizer::{Optimizer, Step};
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg64Mcg;
+use rayon::prelude::*;
 use resvg::{
     render,
     tiny_skia::Pixmap,
@@ -10,14 +26,19 @@ use resvg::{
         fontdb::Database, Options, PostProcessingSteps, Transform, Tree, TreeParsing, TreePostProc,
     },
 };
+use scene::{Layout, Moves, Penalty, Scene, Stop, Style};
 use std::{
-    fmt,
-    fs::{create_dir_all, File},
+    collections::HashMap,
+    fmt::{self, Write as _},
+    fs::{create_dir_all, read_to_string, File},
     io::Write as _,
-    ops::{Add, Div, Mul, Sub},
-    path::Path,
+    iter, mem,
+    ops::{Add, Div, Mul, Range, Sub},
+    path::{Path, PathBuf},
+    process::exit,
+    time::Instant,
 };
-use svgtypes::PathParser;
+use svgtypes::SimplifyingPathParser;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Vec2 {
@@ -73,6 +94,16 @@ fn dot(u: Vec2, v: Vec2) -> f64 {
     u.x * v.x + u.y * v.y
 }
 
+/// Return `v` rotated by `angle` radians, clockwise on screen since the y-axis points down.
+fn rotate(angle: f64, v: Vec2) -> Vec2 {
+    let (s, c) = angle.sin_cos();
+    vec2(c * v.x - s * v.y, s * v.x + c * v.y)
+}
+
+/// The most times `Bezier::flatten` halves a curve, so at most 2^16 pieces come from each one.
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Clone, Copy, Debug)]
 struct Bezier {
     p0: Vec2,
     p1: Vec2,
@@ -81,243 +112,851 @@ struct Bezier {
 }
 
 impl Bezier {
-    fn at(&self, t: f64) -> Vec2 {
+    /// Split this curve in half using de Casteljau's algorithm.
+    fn split(&self) -> (Bezier, Bezier) {
         let &Self { p0, p1, p2, p3 } = self;
-        // https://en.wikipedia.org/wiki/B%C3%A9zier_curve#Cubic_B%C3%A9zier_curves
-        let t2 = t * t;
-        let t3 = t2 * t;
-        let s = 1. - t;
-        let s2 = s * s;
-        let s3 = s2 * s;
-        s3 * p0 + 3. * s2 * t * p1 + 3. * s * t2 * p2 + t3 * p3
-    }
-}
-
-const GLYPHS: [(char, &str); 7] = [
-    ('A', "M0.624 191L69.44 0.599987H121.936L190.752 191H148.048L135.808 155.368H55.568L43.328 191H0.624ZM65.904 125.176H125.472L96.912 42.216H94.464L65.904 125.176Z"),
-    ('G', "M89.008 195.72C31.616 195.72 0.0640001 159 0.0640001 98.616C0.0640001 36.056 34.88 0.151986 88.192 0.151986C128.176 0.151986 162.992 19.736 168.704 62.984H128.448C122.736 40.68 103.968 35.24 88.192 35.24C53.92 35.24 41.408 62.44 41.408 97.256C41.408 132.888 54.736 160.632 89.008 160.632C109.136 160.632 131.712 151.112 131.712 120.92H94.992V88.28H172.24V108.136C172.24 165.8 140.96 195.72 89.008 195.72Z"),
-    ('H', "M115.472 191V112.664H40.944V191H0.144V0.599987H40.944V78.936H115.472V0.599987H156.272V191H115.472Z"),
-    ('I', "M0.144 191V0.599987H40.944V191H0.144Z"),
-    ('P', "M0.144 191V0.599987H77.12C127.712 0.599987 149.744 26.984 149.744 64.248C149.744 101.24 127.712 127.624 77.12 127.624H40.944V191H0.144ZM40.944 92.264H75.76C98.064 92.264 107.856 81.112 107.856 64.248C107.856 47.112 98.064 35.96 75.76 35.96H40.944V92.264Z"),
-    ('R', "M111.392 191L75.216 127.624H40.944V191H0.144V0.599987H77.12C127.712 0.599987 149.744 26.984 149.744 64.248C149.744 91.448 138.048 112.664 111.664 122.184L151.648 191H111.392ZM40.944 92.264H75.76C98.064 92.264 107.856 81.112 107.856 64.248C107.856 47.112 98.064 35.96 75.76 35.96H40.944V92.264Z"),
-    ('S', "M79.988 195.72C30.756 195.72 0.564 172.6 0.564 130.712H42.724C43.54 147.848 53.876 161.992 80.26 161.992C101.748 161.992 114.26 152.744 114.26 137.24C114.26 124.184 105.284 118.2 87.876 114.664L61.22 109.224C31.028 103.512 5.732 89.912 5.732 55.912C5.732 19.192 35.38 0.151986 79.172 0.151986C123.78 0.151986 152.34 19.736 152.34 58.904H110.452C110.996 41.768 97.124 33.88 78.356 33.88C56.324 33.88 48.436 44.76 48.436 55.912C48.436 64.616 53.876 73.048 71.012 76.584L95.492 81.48C140.372 90.728 157.508 108.136 157.508 136.968C157.508 178.04 123.508 195.72 79.988 195.72Z"),
-];
-const BIG: char = 'S';
-const WIDTH: f64 = 158.;
-const HEIGHT: f64 = 196.;
-const SCALE: f64 = 1. / 15.;
-const GAP: f64 = 3.;
-
-type Polygon = Vec<Vec2>;
-
-fn polygonize(path: &str) -> Polygon {
-    let mut points = vec![];
-    let mut x0 = f64::NAN;
-    let mut y0 = f64::NAN;
-    for segment in PathParser::from(path) {
-        use svgtypes::PathSegment::*;
-        match segment.unwrap() {
-            MoveTo { abs, x, y } => {
-                assert!(abs);
-                points.push(vec2(x, y));
-                (x0, y0) = (x, y);
+        let p01 = 0.5 * (p0 + p1);
+        let p12 = 0.5 * (p1 + p2);
+        let p23 = 0.5 * (p2 + p3);
+        let p012 = 0.5 * (p01 + p12);
+        let p123 = 0.5 * (p12 + p23);
+        let p = 0.5 * (p012 + p123);
+        (
+            Bezier {
+                p0,
+                p1: p01,
+                p2: p012,
+                p3: p,
+            },
+            Bezier {
+                p0: p,
+                p1: p123,
+                p2: p23,
+                p3,
+            },
+        )
+    }
+
+    /// Append to `pieces` subcurves of this one, each of which stays within `tolerance` of its
+    /// chord, unless that takes more than `MAX_DEPTH` halvings from a curve `depth` halvings deep.
+    ///
+    /// A curve lies inside the convex hull of its control points, so it can't be farther from its
+    /// chord than its farthest control point is.
+    fn flatten(&self, tolerance: f64, depth: u32, pieces: &mut Vec<Bezier>) {
+        let &Self { p0, p1, p2, p3 } = self;
+        let deviation = segment_distance(p0, p3, p1).max(segment_distance(p0, p3, p2));
+        // the depth limit guards against degenerate inputs like NaN or a zero tolerance, which
+        // would otherwise keep halving forever
+        if deviation <= tolerance || depth >= MAX_DEPTH {
+            pieces.push(*self);
+        } else {
+            let (a, b) = self.split();
+            a.flatten(tolerance, depth + 1, pieces);
+            b.flatten(tolerance, depth + 1, pieces);
+        }
+    }
+}
+
+/// Return the distance from `p` to the line segment from `a` to `b`.
+fn segment_distance(a: Vec2, b: Vec2, p: Vec2) -> f64 {
+    let e = b - a;
+    let w = p - a;
+    let ee = dot(e, e);
+    let t = if ee > 0. {
+        (dot(w, e) / ee).clamp(0., 1.)
+    } else {
+        0.
+    };
+    let d = w - t * e;
+    dot(d, d).sqrt()
+}
+
+/// The largest penalty for an arrangement in which glyphs still count as fitting.
+const FEASIBLE: f64 = 1e-6;
+/// How many steps to take between saving checkpoints alongside the frames. A checkpoint is also
+/// saved at the end, and `run --resume` carries on from one.
+const CHECKPOINT: usize = 1000;
+
+/// A list of loops, filled according to the even-odd rule. Outer boundaries have positive signed
+/// area in SVG coordinates, and holes have negative signed area.
+type Polygon = Vec<Vec<Vec2>>;
+
+/// The glyphs to pack, and the container to pack them into.
+struct Shapes {
+    /// Which glyph each of the glyphs to pack should be.
+    letters: Letters,
+
+    /// Each character along with its SVG path data, in the order given by `Letters::chars`.
+    glyphs: Vec<(char, String)>,
+
+    /// SVG path data for the container, whose bounding box starts at the origin.
+    container: String,
+
+    /// The width of the container's bounding box.
+    width: f64,
+
+    /// The height of the container's bounding box.
+    height: f64,
+
+    layout: Layout,
+    sampling: scene::Sampling,
+    style: Style,
+}
+
+/// How `polygonize` approximates curves by line segments.
+#[derive(Clone, Copy, Debug)]
+struct Flatten {
+    /// The maximum distance between a curve and the polygon edges approximating it.
+    tolerance: f64,
+
+    /// Whether to push edges outward so that the polygon contains the true shape.
+    ///
+    /// Otherwise each edge is a chord of the curve, so the polygon can cut corners by up to
+    /// `tolerance`. If this is set then each edge whose curve bulges outward is replaced by a
+    /// trapezoid reaching past that curve's control points, so the polygons of two shapes only
+    /// overlap if the shapes themselves do, at the cost of a couple of extra vertices per edge.
+    conservative: bool,
+}
+
+/// Return the polygons for SVG path data, approximating curves as specified by `flatten`, one for
+/// each separate piece of the filled shape, like the stem and the dot of an i, largest first.
+///
+/// Any path data is accepted, including relative commands, smooth curves and arcs. Subpaths that
+/// aren't explicitly closed are closed implicitly, just as they would be when filled. Each polygon
+/// has its outer boundary first and then its holes, and is oriented as described for `Polygon`, no
+/// matter which direction each subpath was drawn in. Fails if the path encloses nothing.
+fn polygonize(path: &str, flatten: Flatten) -> Result<Vec<Polygon>, String> {
+    // each of the `points` has an entry in `curves` for the piece of curve leading up to it
+    let close = |loops: &mut Vec<(Vec<Vec2>, Vec<Option<Bezier>>)>,
+                 points: &mut Vec<Vec2>,
+                 curves: &mut Vec<Option<Bezier>>| {
+        if let (Some(&first), Some(&last)) = (points.first(), points.last()) {
+            let d = last - first;
+            if dot(d, d) < 1e-18 {
+                points.pop().unwrap();
+                curves[0] = curves.pop().unwrap();
             }
-            LineTo { abs, x, y } => {
-                assert!(abs);
-                points.push(vec2(x, y));
-                (x0, y0) = (x, y);
+        }
+        if points.len() > 2 {
+            loops.push((mem::take(points), mem::take(curves)));
+        }
+        points.clear();
+        curves.clear();
+    };
+
+    let mut loops = vec![];
+    let mut points = vec![];
+    let mut curves = vec![];
+    let mut pieces = vec![];
+    let mut p0 = vec2(f64::NAN, f64::NAN);
+    for segment in SimplifyingPathParser::from(path) {
+        use svgtypes::SimplePathSegment::*;
+        let curve = match segment.unwrap() {
+            MoveTo { x, y } => {
+                close(&mut loops, &mut points, &mut curves);
+                p0 = vec2(x, y);
+                points.push(p0);
+                curves.push(None);
+                continue;
             }
-            HorizontalLineTo { abs, x } => {
-                assert!(abs);
-                points.push(vec2(x, y0));
-                x0 = x;
+            LineTo { x, y } => {
+                let p = vec2(x, y);
+                if points.last() != Some(&p) {
+                    points.push(p);
+                    curves.push(None);
+                }
+                p0 = p;
+                continue;
             }
-            VerticalLineTo { abs, y } => {
-                assert!(abs);
-                points.push(vec2(x0, y));
-                y0 = y;
+            Quadratic { x1, y1, x, y } => {
+                // degree elevation, so that we can reuse the cubic code
+                let p1 = vec2(x1, y1);
+                let p3 = vec2(x, y);
+                Bezier {
+                    p0,
+                    p1: p0 + 2. / 3. * (p1 - p0),
+                    p2: p3 + 2. / 3. * (p1 - p3),
+                    p3,
+                }
             }
             CurveTo {
-                abs,
                 x1,
                 y1,
                 x2,
                 y2,
                 x,
                 y,
-            } => {
-                assert!(abs);
-                let curve = Bezier {
-                    p0: vec2(x0, y0),
-                    p1: vec2(x1, y1),
-                    p2: vec2(x2, y2),
-                    p3: vec2(x, y),
-                };
-                points.push(curve.at(1. / 5.));
-                points.push(curve.at(2. / 5.));
-                points.push(curve.at(3. / 5.));
-                points.push(curve.at(4. / 5.));
-                points.push(vec2(x, y));
-                (x0, y0) = (x, y);
+            } => Bezier {
+                p0,
+                p1: vec2(x1, y1),
+                p2: vec2(x2, y2),
+                p3: vec2(x, y),
+            },
+            ClosePath => {
+                close(&mut loops, &mut points, &mut curves);
+                continue;
             }
-            ClosePath { abs } => {
-                assert!(abs);
-                assert_eq!(points.first().unwrap(), points.last().unwrap());
-                points.pop().unwrap();
-                return points;
+        };
+        pieces.clear();
+        curve.flatten(flatten.tolerance, 0, &mut pieces);
+        for piece in &pieces {
+            if points.last() != Some(&piece.p3) {
+                points.push(piece.p3);
+                curves.push(Some(*piece));
             }
-            _ => unimplemented!(),
         }
+        p0 = curve.p3;
     }
-    panic!()
+    close(&mut loops, &mut points, &mut curves);
+    if loops.is_empty() {
+        return Err("path should enclose some area".to_owned());
+    }
+
+    // a loop is a hole if it's inside an odd number of the others, in which case it belongs to the
+    // innermost of them, regardless of which direction it was drawn in
+    let n = loops.len();
+    let around: Vec<Vec<usize>> = (0..n)
+        .map(|i| {
+            let p = loops[i].0[0];
+            (0..n)
+                .filter(|&j| j != i && inside(&loops[j].0, p))
+                .collect()
+        })
+        .collect();
+    let parents: Vec<Option<usize>> = (0..n)
+        .map(|i| match around[i].len() % 2 {
+            0 => None,
+            _ => around[i].iter().copied().max_by_key(|&j| around[j].len()),
+        })
+        .collect();
+    let mut outers: Vec<usize> = (0..n).filter(|&i| parents[i].is_none()).collect();
+    outers.sort_by(|&i, &j| area(&loops[j].0).abs().total_cmp(&area(&loops[i].0).abs()));
+    let mut polygons: Vec<Polygon> = outers.iter().map(|_| vec![]).collect();
+    let mut loops: Vec<_> = loops.into_iter().map(Some).collect();
+    // each outer boundary comes first in its component, followed by its holes in their given order
+    for (k, &i) in outers.iter().enumerate() {
+        let members = iter::once(i).chain((0..n).filter(|&j| parents[j] == Some(i)));
+        for j in members {
+            let (mut points, curves) = loops[j].take().unwrap();
+            let s = if j == i { 1. } else { -1. } * area(&points).signum();
+            if flatten.conservative {
+                // for each edge, `n` points away from the filled side
+                let mut bulged = vec![];
+                for (&p3, curve) in points.iter().zip(curves) {
+                    if let Some(Bezier { p0, p1, p2, .. }) = curve {
+                        let len = dot(p3 - p0, p3 - p0).sqrt();
+                        let e = (p3 - p0) / len;
+                        let n = s * vec2(e.y, -e.x);
+                        let out = dot(p1 - p0, n).max(dot(p2 - p0, n));
+                        if out > 0. {
+                            let lo = dot(p1 - p0, e).min(dot(p2 - p0, e)).min(0.);
+                            let hi = dot(p1 - p0, e).max(dot(p2 - p0, e)).max(len);
+                            bulged.push(p0 + lo * e + out * n);
+                            bulged.push(p0 + hi * e + out * n);
+                        }
+                    }
+                    bulged.push(p3);
+                }
+                points = bulged;
+            }
+            if s < 0. {
+                points.reverse();
+            }
+            polygons[k].push(points);
+        }
+    }
+    Ok(polygons)
+}
+
+/// Whether `p` is inside the loop through `points`.
+fn inside(points: &[Vec2], p: Vec2) -> bool {
+    let n = points.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Return the signed area of the polygon through `points`.
+fn area(points: &[Vec2]) -> f64 {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let (u, v) = (points[i], points[(i + 1) % n]);
+            u.x * v.y - u.y * v.x
+        })
+        .sum::<f64>()
+        / 2.
 }
 
 // https://iquilezles.org/articles/distfunctions2d/
-fn sd_polygon(v: &[Vec2], p: Vec2) -> (f64, Vec2) {
-    let n = v.len();
-    let u = p - v[0];
+fn sd_polygon(loops: &[Vec<Vec2>], p: Vec2) -> (f64, Vec2) {
+    let u = p - loops[0][0];
     let mut d = dot(u, u);
     let mut dp = 2. * u;
     let mut s = 1.0;
-    let mut i = 0;
-    let mut j = n - 1;
-    while i < n {
-        let e = v[j] - v[i];
-        let w = p - v[i];
-        let we = dot(w, e);
-        let ee = dot(e, e);
-        let r = we / ee;
-        let rc = r.clamp(0.0, 1.0);
-        let b = w - e * rc;
-        let bb = dot(b, b);
-        if bb < d {
-            d = bb;
-            let db = 2. * b;
-            let drc = -dot(e, db);
-            let dr = if (0.0..=1.0).contains(&r) { drc } else { 0. };
-            let dwe = dr / ee;
-            let dw = db + dwe * e;
-            dp = dw;
-        }
-        let c = [p.y >= v[i].y, p.y < v[j].y, e.x * w.y > e.y * w.x];
-        if c.iter().all(|&a| a) || c.iter().all(|&a| !a) {
-            s *= -1.0;
-        }
-        j = i;
-        i += 1;
+    for v in loops {
+        let n = v.len();
+        let mut i = 0;
+        let mut j = n - 1;
+        while i < n {
+            let e = v[j] - v[i];
+            let w = p - v[i];
+            let we = dot(w, e);
+            let ee = dot(e, e);
+            let r = we / ee;
+            let rc = r.clamp(0.0, 1.0);
+            let b = w - e * rc;
+            let bb = dot(b, b);
+            if bb < d {
+                d = bb;
+                let db = 2. * b;
+                let drc = -dot(e, db);
+                let dr = if (0.0..=1.0).contains(&r) { drc } else { 0. };
+                let dwe = dr / ee;
+                let dw = db + dwe * e;
+                dp = dw;
+            }
+            let c = [p.y >= v[i].y, p.y < v[j].y, e.x * w.y > e.y * w.x];
+            if c.iter().all(|&a| a) || c.iter().all(|&a| !a) {
+                s *= -1.0;
+            }
+            j = i;
+            i += 1;
+        }
     }
     let z = s * d.sqrt();
     (z, dp / (2. * z))
 }
 
+/// A Minkowski sum sampled over a grid of angles and log-scales, indexed by angle and then scale.
+///
+/// Along each axis the samples are evenly spaced, with the middle one at zero.
+type Samples = Vec<Vec<Polygon>>;
+
+/// Return the indices of the samples on either side of `x`, how far `x` is from the first toward
+/// the second, and whether `x` is within range of the samples at all.
+///
+/// There are `len` samples, `step` apart, and `x` is clamped to their range.
+fn locate(x: f64, step: f64, len: usize) -> (usize, usize, f64, bool) {
+    if len == 1 {
+        return (0, 0, 0., false);
+    }
+    let t = x / step + (len / 2) as f64;
+    let k = (t.floor().max(0.) as usize).min(len - 2);
+    let f = (t - k as f64).clamp(0., 1.);
+    (k, k + 1, f, (0.0..=(len - 1) as f64).contains(&t))
+}
+
+/// Like `sd_polygon`, but for a polygon that turns with `angle` and grows with the log-scale
+/// `scale`, also returning the derivatives with respect to each of those.
+///
+/// The polygon is given by `samples` with the spacing `steps` between them along each axis, and
+/// the distance is interpolated bilinearly between them.
+fn sd_sampled(
+    samples: &Samples,
+    steps: (f64, f64),
+    angle: f64,
+    scale: f64,
+    p: Vec2,
+) -> (f64, Vec2, f64, f64) {
+    let (a0, a1, fa, in_a) = locate(angle, steps.0, samples.len());
+    let (b0, b1, fb, in_b) = locate(scale, steps.1, samples[0].len());
+    // avoid recomputing the same sample along an axis that isn't being varied
+    let (z00, g00) = sd_polygon(&samples[a0][b0], p);
+    let (z10, g10) = if a1 == a0 {
+        (z00, g00)
+    } else {
+        sd_polygon(&samples[a1][b0], p)
+    };
+    let (z01, g01) = if b1 == b0 {
+        (z00, g00)
+    } else {
+        sd_polygon(&samples[a0][b1], p)
+    };
+    let (z11, g11) = if a1 == a0 {
+        (z01, g01)
+    } else if b1 == b0 {
+        (z10, g10)
+    } else {
+        sd_polygon(&samples[a1][b1], p)
+    };
+    let z0 = (1. - fa) * z00 + fa * z10;
+    let z1 = (1. - fa) * z01 + fa * z11;
+    let g0 = (1. - fa) * g00 + fa * g10;
+    let g1 = (1. - fa) * g01 + fa * g11;
+    let da = if in_a {
+        ((1. - fb) * (z10 - z00) + fb * (z11 - z01)) / steps.0
+    } else {
+        0.
+    };
+    let db = if in_b { (z1 - z0) / steps.1 } else { 0. };
+    ((1. - fb) * z0 + fb * z1, (1. - fb) * g0 + fb * g1, da, db)
+}
+
+/// Like `sd_sampled`, but for the union of several sampled polygons, which is as far from `p` as
+/// the nearest of them.
+fn sd_union(
+    samples: &[Samples],
+    steps: (f64, f64),
+    angle: f64,
+    scale: f64,
+    p: Vec2,
+) -> (f64, Vec2, f64, f64) {
+    samples
+        .iter()
+        .map(|samples| sd_sampled(samples, steps, angle, scale, p))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .unwrap()
+}
+
+#[derive(Clone, Debug)]
 struct Glyphs {
     indices: Vec<usize>,
     hues: Vec<f64>,
+    /// All the x-coordinates, then all the y-coordinates, then all the angles in radians, then all
+    /// the natural logs of the scales relative to `Layout::scale`.
     coords: Vec<f64>,
 }
 
-fn init(seed: u64, n: usize) -> Glyphs {
-    let mut rng = Pcg64Mcg::seed_from_u64(seed);
-    let mut coords: Vec<_> = (0..n).map(|_| rng.gen_range(0.0..WIDTH)).collect();
-    coords.extend((0..n).map(|_| rng.gen_range(0.0..HEIGHT)));
+impl Glyphs {
+    /// Add a glyph, given its index, hue, and its coordinates in the same order as `coords`.
+    fn push(&mut self, index: usize, hue: f64, coords: [f64; 4]) {
+        let n = self.indices.len();
+        self.indices.push(index);
+        self.hues.push(hue);
+        // insert from the back, so that the earlier positions stay put
+        for (k, &c) in coords.iter().enumerate().rev() {
+            self.coords.insert((k + 1) * n, c);
+        }
+    }
+
+    /// Remove glyph `i`, the opposite of `push`.
+    fn remove(&mut self, i: usize) {
+        let n = self.indices.len();
+        self.indices.remove(i);
+        self.hues.remove(i);
+        // remove from the back, so that the earlier positions stay put
+        for k in (0..4).rev() {
+            self.coords.remove(k * n + i);
+        }
+    }
+}
+
+fn init(shapes: &Shapes, rng: &mut Pcg64Mcg, n: usize) -> Glyphs {
+    let mut coords: Vec<_> = (0..n).map(|_| rng.gen_range(0.0..shapes.width)).collect();
+    coords.extend((0..n).map(|_| rng.gen_range(0.0..shapes.height)));
+    let indices = (0..n).map(|i| shapes.letters.pick(rng, i)).collect();
+    let hues = (0..n).map(|_| rng.gen_range(0.0..360.0)).collect();
+    let tilt = shapes.layout.tilt.to_radians();
+    coords.extend((0..n).map(|_| rng.gen_range(-tilt..=tilt)));
+    let grow = shapes.layout.grow.ln();
+    coords.extend((0..n).map(|_| rng.gen_range(-grow..=grow)));
     Glyphs {
-        indices: (0..n).map(|_| rng.gen_range(0..GLYPHS.len())).collect(),
-        hues: (0..n).map(|_| rng.gen_range(0.0..360.0)).collect(),
+        indices,
+        hues,
         coords,
     }
 }
 
+/// Minkowski sums for every glyph or pair of glyphs, sampled as described for `sd_sampled`.
 struct Sums {
-    contains: Vec<Polygon>,
-    pairs: Vec<Vec<Polygon>>,
+    /// The angle between consecutive samples in radians, and the same for log-scales.
+    steps: (f64, f64),
+    /// For each glyph and each piece of it, one sum per piece of the container. Each piece of the
+    /// glyph has to be inside one of the pieces of the container.
+    contains: Vec<Vec<Vec<Samples>>>,
+    /// For each glyph, one polygon per piece of it and hole in the container, which that piece must
+    /// stay out of.
+    excludes: Vec<Vec<Samples>>,
+    /// One sum for each piece of the first glyph and piece of the second, which overlap if any of
+    /// those pieces do. Sampled by the angle and log-scale of the second glyph relative to the
+    /// first, so twice as widely along each axis.
+    pairs: Vec<Vec<Vec<Samples>>>,
+    /// For each pair, the farthest that any sample reaches from the origin.
+    radii: Vec<Vec<f64>>,
+    /// The largest of the `radii`.
+    radius: f64,
+    /// The layout that the sums were computed for.
+    layout: Layout,
+}
+
+/// Return every pair of indices `(i, j)` with `i < j` whose points are within `d` of each other,
+/// along with some that aren't, in lexicographic order.
+///
+/// Points are bucketed into a grid of cells `d` wide, so only neighboring cells need comparing.
+fn near_pairs(x: &[f64], y: &[f64], d: f64) -> Vec<(usize, usize)> {
+    let cell = |i: usize| ((x[i] / d).floor() as i64, (y[i] / d).floor() as i64);
+    let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for i in 0..x.len() {
+        cells.entry(cell(i)).or_default().push(i);
+    }
+    let mut pairs = vec![];
+    for i in 0..x.len() {
+        let (a, b) = cell(i);
+        for da in -1..=1 {
+            for db in -1..=1 {
+                if let Some(js) = cells.get(&(a + da, b + db)) {
+                    pairs.extend(js.iter().filter(|&&j| j > i).map(|&j| (i, j)));
+                }
+            }
+        }
+    }
+    // the order in which the penalties are summed affects the result down to the last bit
+    pairs.sort_unstable();
+    pairs
 }
 
 fn val_and_grad(sums: &Sums, indices: &[usize], coords: &[f64], grad: &mut [f64]) -> f64 {
     grad.fill(0.);
     let n = indices.len();
-    let (x, y) = coords.split_at(n);
-    let (dx, dy) = grad.split_at_mut(n);
+    let (x, rest) = coords.split_at(n);
+    let (y, rest) = rest.split_at(n);
+    let (angles, scales) = rest.split_at(n);
+    let (dx, rest) = grad.split_at_mut(n);
+    let (dy, rest) = rest.split_at_mut(n);
+    let (dt, ds) = rest.split_at_mut(n);
+    let layout = &sums.layout;
+    let gap = layout.gap;
+    let t: Vec<f64> = angles.iter().map(|&a| layout.tilt(a)).collect();
+    let s: Vec<f64> = scales.iter().map(|&b| layout.grow(b)).collect();
+    // the signed distances are where the time goes, so they're computed in parallel, and then the
+    // penalties are summed in the same order as they would be serially, so that the result is the
+    // same to the bit however the work was split between threads
+    let contained: Vec<Vec<(f64, Vec2, f64, f64)>> = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let p = vec2(x[i], y[i]);
+            let within = (sums.contains[indices[i]].iter())
+                .map(|pieces| sd_union(pieces, sums.steps, t[i], s[i], p));
+            let without = (sums.excludes[indices[i]].iter())
+                .map(|samples| sd_sampled(samples, sums.steps, t[i], s[i], p));
+            within.chain(without).collect()
+        })
+        .collect();
     let mut fx = 0.;
-    for i in 0..n {
-        let (z, dp) = sd_polygon(&sums.contains[indices[i]], vec2(x[i], y[i]));
-        let w = z + GAP;
-        if w > 0. {
-            fx += w * w;
-            dx[i] += 2. * w * dp.x;
-            dy[i] += 2. * w * dp.y;
-        }
-    }
-    for i in 0..n {
-        for j in (i + 1)..n {
-            let (z, dp) = sd_polygon(
-                &sums.pairs[indices[i]][indices[j]],
-                (vec2(x[j], y[j]) - vec2(x[i], y[i])) / SCALE,
-            );
-            let w = GAP - SCALE * z;
+    for (i, dists) in contained.iter().enumerate() {
+        let (within, without) = dists.split_at(sums.contains[indices[i]].len());
+        for &(z, dp, da, db) in within {
+            let w = z + gap;
             if w > 0. {
                 fx += w * w;
                 dx[i] += 2. * w * dp.x;
                 dy[i] += 2. * w * dp.y;
-                dx[j] -= 2. * w * dp.x;
-                dy[j] -= 2. * w * dp.y;
+                dt[i] += 2. * w * da;
+                ds[i] += 2. * w * db;
+            }
+        }
+        for &(z, dp, da, db) in without {
+            let w = gap - z;
+            if w > 0. {
+                fx += w * w;
+                dx[i] -= 2. * w * dp.x;
+                dy[i] -= 2. * w * dp.y;
+                dt[i] -= 2. * w * da;
+                ds[i] -= 2. * w * db;
             }
         }
     }
+    // no pair farther apart than this can pass the check against `radii` below
+    let reach = layout.scale * layout.grow * sums.radius + gap;
+    let pairs = near_pairs(x, y, reach);
+    let overlaps: Vec<_> = pairs
+        .par_iter()
+        .map(|&(i, j)| {
+            // the sum is for glyph `i` unrotated at `layout.scale`, so transform the offset to match
+            let k = layout.scale * s[i].exp();
+            let u = rotate(-t[i], vec2(x[j], y[j]) - vec2(x[i], y[i])) / k;
+            // the boundary of the sum is within its radius, so the signed distance is at least
+            // the distance beyond that, which here would be too far for any penalty
+            let r = sums.radii[indices[i]][indices[j]] + gap / k;
+            if dot(u, u) > r * r {
+                return None;
+            }
+            let dist = sd_union(
+                &sums.pairs[indices[i]][indices[j]],
+                sums.steps,
+                t[j] - t[i],
+                s[j] - s[i],
+                u,
+            );
+            Some((k, u, dist))
+        })
+        .collect();
+    for (&(i, j), overlap) in pairs.iter().zip(overlaps) {
+        let Some((k, u, (z, dp, da, db))) = overlap else {
+            continue;
+        };
+        let w = gap - k * z;
+        if w > 0. {
+            fx += w * w;
+            let g = rotate(t[i], dp);
+            dx[i] += 2. * w * g.x;
+            dy[i] += 2. * w * g.y;
+            dx[j] -= 2. * w * g.x;
+            dy[j] -= 2. * w * g.y;
+            dt[i] += 2. * w * k * (da - dot(dp, vec2(u.y, -u.x)));
+            dt[j] -= 2. * w * k * da;
+            ds[i] += 2. * w * k * (db + dot(dp, u) - z);
+            ds[j] -= 2. * w * k * db;
+        }
+    }
+    for i in 0..n {
+        if layout.tilt == 0. {
+            // the angles are fixed at zero
+            dt[i] = 0.;
+        } else if angles[i] != t[i] {
+            dt[i] = 2. * (angles[i] - t[i]);
+            fx += (angles[i] - t[i]).powi(2);
+        }
+        if layout.grow == 1. {
+            // the scales are fixed at `layout.scale`
+            ds[i] = 0.;
+        } else if scales[i] != s[i] {
+            ds[i] = 2. * (scales[i] - s[i]);
+            fx += (scales[i] - s[i]).powi(2);
+        } else {
+            // reward each glyph in proportion to its area
+            let area = (2. * s[i]).exp();
+            fx -= layout.growth * area;
+            ds[i] -= 2. * layout.growth * area;
+        }
+    }
     fx
 }
 
+/// Return the objective for `glyphs` without the reward for growth, which is zero exactly when
+/// every glyph is inside the container and at least `Layout::gap` away from every other.
+fn penalty(sums: &Sums, glyphs: &Glyphs) -> f64 {
+    let mut grad = vec![0.; glyphs.coords.len()];
+    let fx = val_and_grad(sums, &glyphs.indices, &glyphs.coords, &mut grad);
+    fx + reward(&sums.layout, &glyphs.coords)
+}
+
+/// Return how much `val_and_grad` subtracts from the objective at `coords` to reward growth.
+fn reward(layout: &Layout, coords: &[f64]) -> f64 {
+    if layout.grow == 1. {
+        return 0.;
+    }
+    // `val_and_grad` only rewards glyphs whose scales are within range
+    coords[3 * coords.len() / 4..]
+        .iter()
+        .filter(|&&b| layout.grow(b) == b)
+        .map(|&b| layout.growth * (2. * b).exp())
+        .sum()
+}
+
+/// Return the position with the most clearance for a new glyph `index`, untilted and at its
+/// smallest scale, among `glyphs` in the container.
+///
+/// Candidates are taken from a grid over the container's bounding box, `step` units apart.
+fn free_spot(shapes: &Shapes, sums: &Sums, glyphs: &Glyphs, index: usize, step: f64) -> Vec2 {
+    let n = glyphs.indices.len();
+    let (x, rest) = glyphs.coords.split_at(n);
+    let (y, rest) = rest.split_at(n);
+    let (angles, scales) = rest.split_at(n);
+    let layout = &shapes.layout;
+    let s = -layout.grow.ln();
+    let mut best = (f64::NEG_INFINITY, vec2(0., 0.));
+    for a in 0..=(shapes.width / step) as usize {
+        for b in 0..=(shapes.height / step) as usize {
+            let v = vec2(a as f64 * step, b as f64 * step);
+            let mut clearance = f64::INFINITY;
+            for pieces in &sums.contains[index] {
+                let (z, ..) = sd_union(pieces, sums.steps, 0., s, v);
+                clearance = clearance.min(-z);
+            }
+            for hole in &sums.excludes[index] {
+                let (z, ..) = sd_sampled(hole, sums.steps, 0., s, v);
+                clearance = clearance.min(z);
+            }
+            for i in 0..n {
+                if clearance <= best.0 {
+                    break;
+                }
+                let (t, b) = (layout.tilt(angles[i]), layout.grow(scales[i]));
+                let k = layout.scale * b.exp();
+                let u = rotate(-t, v - vec2(x[i], y[i])) / k;
+                // as in `val_and_grad`, the radius bounds how close this glyph could be
+                if k * (dot(u, u).sqrt() - sums.radii[glyphs.indices[i]][index]) >= clearance {
+                    continue;
+                }
+                let samples = &sums.pairs[glyphs.indices[i]][index];
+                let (z, ..) = sd_union(samples, sums.steps, -t, s - b, u);
+                clearance = clearance.min(k * z);
+            }
+            if clearance > best.0 {
+                best = (clearance, v);
+            }
+        }
+    }
+    best.1
+}
+
+/// Why `optimize` stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Reason {
+    GradNorm,
+    RelChange,
+    MaxSteps,
+    Time,
+    Penalty,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Reason::GradNorm => "the gradient vanished",
+            Reason::RelChange => "the objective stopped changing",
+            Reason::MaxSteps => "it ran out of steps",
+            Reason::Time => "it ran out of time",
+            Reason::Penalty => "the glyphs fit",
+        })
+    }
+}
+
+/// Return the optimizer named by `settings`: `lbfgs`, `momentum` for gradient descent with
+/// momentum, `adam`, or `cg` for nonlinear conjugate gradient.
+fn get_optimizer(settings: &scene::Optimizer) -> Result<Box<dyn Optimizer>, String> {
+    let cfg = lbfgs::Config::from(settings.lbfgs);
+    match settings.name.as_str() {
+        "lbfgs" => Ok(Box::new(optimizer::Lbfgs(cfg))),
+        "momentum" => Ok(Box::new(settings.momentum)),
+        "adam" => Ok(Box::new(settings.adam)),
+        "cg" => Ok(Box::new(optimizer::ConjugateGradient(lbfgs::Config {
+            wolfe: settings.cg.wolfe,
+            max_steps: settings.cg.max_steps,
+            ..cfg
+        }))),
+        name => Err(format!(
+            "{name:?} should be one of lbfgs, momentum, adam or cg"
+        )),
+    }
+}
+
+/// Optimize from `start` until `stop` says otherwise, calling `callback` before the first step and
+/// after each one, and returning the result along with its objective value and the reason
+/// optimization stopped.
+///
+/// If `checkpoint` is given, a checkpoint is saved there after every `CHECKPOINT` steps and at the
+/// end. Resuming from one takes the same steps as if optimization had never stopped, except that
+/// the time limit starts over.
 fn optimize(
+    shapes: &Shapes,
     sums: &Sums,
-    mut glyphs: Glyphs,
-    mut callback: impl FnMut(Option<&lbfgs::Info>, &[usize], &[f64], &[f64]),
-) -> (Glyphs, f64) {
-    callback(None, &glyphs.indices, &glyphs.hues, &glyphs.coords);
-    let cfg = lbfgs::Config {
-        m: 17,
-        armijo: 0.001,
-        wolfe: 0.9,
-        min_interval: 1e-9,
-        max_steps: 10,
-        epsd: 1e-11,
-    };
-    let mut state = lbfgs::first_step(
-        cfg,
-        |coords, grad| val_and_grad(sums, &glyphs.indices, coords, grad),
-        &mut glyphs.coords,
-    );
-    callback(None, &glyphs.indices, &glyphs.hues, &glyphs.coords);
-    let mut fx = f64::NAN;
-    lbfgs::step_until(
-        cfg,
-        |coords, grad| val_and_grad(sums, &glyphs.indices, coords, grad),
-        &mut glyphs.coords,
-        &mut state,
-        |info| {
-            callback(Some(&info), &glyphs.indices, &glyphs.hues, info.x);
-            if info.fx == fx {
-                Some(())
-            } else {
-                fx = info.fx;
-                None
+    optimizer: &dyn Optimizer,
+    stop: Stop,
+    start: Checkpoint,
+    checkpoint: Option<&Path>,
+    mut callback: impl FnMut(Option<&Step>, &[usize], &[f64], &[f64]),
+) -> (Glyphs, f64, Reason) {
+    let Checkpoint {
+        mut glyphs,
+        mut steps,
+        mut fx,
+        mut failures,
+        state,
+    } = start;
+    let start = Instant::now();
+    if steps == 0 {
+        callback(None, &glyphs.indices, &glyphs.hues, &glyphs.coords);
+    }
+    let n = glyphs.indices.len();
+    let layout = &shapes.layout;
+    let (tilt, grow) = (layout.tilt.to_radians(), layout.grow.ln());
+    let (lower, upper): (Vec<f64>, Vec<f64>) = [
+        (0., shapes.width),
+        (0., shapes.height),
+        (-tilt, tilt),
+        (-grow, grow),
+    ]
+    .into_iter()
+    .flat_map(|bound| iter::repeat_n(bound, n))
+    .unzip();
+    let chars: String = shapes.glyphs.iter().map(|&(c, _)| c).collect();
+    let mut reason = None;
+    let f =
+        &mut |coords: &[f64], grad: &mut [f64]| val_and_grad(sums, &glyphs.indices, coords, grad);
+    let bounds = layout.bounded.then_some((&lower[..], &upper[..]));
+    let stop = &mut |step: Step| {
+        callback(Some(&step), &glyphs.indices, &glyphs.hues, step.x);
+        steps += 1;
+        // `step.fx` and `step.grad` are both at the point before this step, and with bounds,
+        // only the part of the gradient that doesn't push past them counts
+        let Step { x0: x, grad, .. } = step;
+        let norm = if layout.bounded {
+            (0..x.len())
+                .map(|i| ((x[i] - grad[i]).clamp(lower[i], upper[i]) - x[i]).powi(2))
+                .sum::<f64>()
+                .sqrt()
+        } else {
+            grad.iter().map(|g| g * g).sum::<f64>().sqrt()
+        };
+        // the glyphs fitting is the most telling reason, so it's reported over the others
+        reason = if (stop.penalty(layout)).is_some_and(|p| step.fx + reward(layout, x) <= p) {
+            Some(Reason::Penalty)
+        } else if norm <= stop.grad_norm {
+            Some(Reason::GradNorm)
+        } else if failures != 1 && (fx - step.fx).abs() <= stop.rel_change * fx.abs() {
+            // after a failed line search the point may not have moved at all, but the next
+            // step starts over from steepest descent, so give that a chance
+            Some(Reason::RelChange)
+        } else if stop.max_steps > 0 && steps >= stop.max_steps {
+            Some(Reason::MaxSteps)
+        } else if stop.time > 0. && start.elapsed().as_secs_f64() >= stop.time {
+            Some(Reason::Time)
+        } else {
+            None
+        };
+        fx = step.fx;
+        failures = if step.found { 0 } else { failures + 1 };
+        if let Some(path) = checkpoint.filter(|_| steps % CHECKPOINT == 0 || reason.is_some()) {
+            let glyphs = Glyphs {
+                indices: glyphs.indices.clone(),
+                hues: glyphs.hues.clone(),
+                coords: step.x.to_vec(),
+            };
+            Checkpoint {
+                glyphs,
+                steps,
+                fx,
+                failures,
+                state: Some((step.state)()),
             }
-        },
-    );
-    (glyphs, fx)
+            .save(path, &chars)
+            .unwrap_or_else(|e| fail(format!("{}: {e}", path.display())));
+        }
+        reason.is_some()
+    };
+    match state {
+        Some(state) => optimizer.resume(f, bounds, &mut glyphs.coords, state, stop),
+        None => optimizer.minimize(f, bounds, &mut glyphs.coords, stop),
+    }
+    (glyphs, fx, reason.unwrap())
 }
 
-fn polygon(w: &mut impl fmt::Write, points: &[Vec2]) -> fmt::Result {
-    let x0 = points.iter().map(|v| v.x).reduce(f64::min).unwrap();
-    let y0 = points.iter().map(|v| v.y).reduce(f64::min).unwrap();
+fn polygon(w: &mut impl fmt::Write, loops: &[Vec<Vec2>]) -> fmt::Result {
+    let points = || loops.iter().flatten();
+    let x0 = points().map(|v| v.x).reduce(f64::min).unwrap();
+    let y0 = points().map(|v| v.y).reduce(f64::min).unwrap();
     writeln!(
         w,
         r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{x0} {y0} {} {}">"#,
-        points.iter().map(|v| v.x).reduce(f64::max).unwrap() - x0,
-        points.iter().map(|v| v.y).reduce(f64::max).unwrap() - y0,
+        points().map(|v| v.x).reduce(f64::max).unwrap() - x0,
+        points().map(|v| v.y).reduce(f64::max).unwrap() - y0,
     )?;
-    let Vec2 { x, y } = points[0];
-    write!(w, "  <polygon points=\"{x},{y}")?;
-    for Vec2 { x, y } in &points[1..] {
-        write!(w, " {x},{y}")?;
+    write!(w, "  <path fill-rule=\"evenodd\" d=\"")?;
+    for points in loops {
+        let Vec2 { x, y } = points[0];
+        write!(w, "M{x},{y}")?;
+        for Vec2 { x, y } in &points[1..] {
+            write!(w, " {x},{y}")?;
+        }
+        write!(w, "Z")?;
     }
     writeln!(w, "\" />")?;
     writeln!(w, "</svg>")?;
@@ -358,27 +997,33 @@ fn hsv_to_rgb(h0: f64, s0: f64, v0: f64) -> (f64, f64, f64) {
 
 fn arrangement(
     w: &mut impl fmt::Write,
+    shapes: &Shapes,
     indices: &[usize],
     hues: &[f64],
     coords: &[f64],
 ) -> fmt::Result {
-    let n = hues.len();
-    let &(_, big) = GLYPHS.iter().find(|&&(c, _)| c == BIG).unwrap();
+    let (layout, style) = (&shapes.layout, &shapes.style);
+    let big = &shapes.container;
     writeln!(
         w,
-        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {HEIGHT}">"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}">"#,
+        shapes.width, shapes.height,
+    )?;
+    writeln!(
+        w,
+        r#"  <path fill="{}" fill-rule="evenodd" d="{big}" />"#,
+        style.container,
     )?;
-    writeln!(w, r##"  <path fill="#C1C1C1" d="{big}" />"##)?;
     for (i, (&j, &h)) in indices.iter().zip(hues.iter()).enumerate() {
-        let (_, path) = GLYPHS[j];
-        let (r, g, b) = hsv_to_rgb(h, 60., 100.);
+        let (_, path) = &shapes.glyphs[j];
+        let (r, g, b) = hsv_to_rgb(h, style.saturation, style.value);
+        let (x, y, angle, k) = placement(layout, coords, i);
         writeln!(
             w,
-            r##"  <path paint-order="stroke" fill="rgb({r} {g} {b})" stroke="#080664" stroke-opacity="{}" stroke-width="{}" stroke-linejoin="round" transform="translate({} {}) scale({SCALE} {SCALE})" d="{}" />"##,
-            0xea as f64 / 255.,
-            1.5 / SCALE,
-            coords[i],
-            coords[n + i],
+            r#"  <path paint-order="stroke" fill="rgb({r} {g} {b})" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linejoin="round" transform="translate({x} {y}) rotate({angle}) scale({k} {k})" d="{}" />"#,
+            style.stroke,
+            style.stroke_opacity,
+            style.stroke_width / k,
             path,
         )?;
     }
@@ -386,6 +1031,86 @@ fn arrangement(
     Ok(())
 }
 
+/// Return where glyph `i` is drawn given all the `coords`, along with its angle in degrees and its
+/// scale.
+fn placement(layout: &Layout, coords: &[f64], i: usize) -> (f64, f64, f64, f64) {
+    let n = coords.len() / 4;
+    let angle = layout.tilt(coords[2 * n + i]).to_degrees();
+    let k = layout.scale * layout.grow(coords[3 * n + i]).exp();
+    (coords[i], coords[n + i], angle, k)
+}
+
+/// Like `arrangement`, but animating the glyphs through each of `frames`, given as coordinates,
+/// over `duration` seconds. Each move from one frame to the next takes the same time, interpolating
+/// linearly, and the animation stops on the last frame.
+///
+/// The animation uses SMIL, so that the SVG stands alone. Viewers that don't support it show the
+/// last frame.
+fn animation(
+    w: &mut impl fmt::Write,
+    shapes: &Shapes,
+    indices: &[usize],
+    hues: &[f64],
+    frames: &[Vec<f64>],
+    duration: f64,
+) -> fmt::Result {
+    let (layout, style) = (&shapes.layout, &shapes.style);
+    let big = &shapes.container;
+    let last = frames.last().unwrap();
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}">"#,
+        shapes.width, shapes.height,
+    )?;
+    writeln!(
+        w,
+        r#"  <path fill="{}" fill-rule="evenodd" d="{big}" />"#,
+        style.container,
+    )?;
+    let timing = format!(r#"dur="{duration}s" fill="freeze""#);
+    for (i, (&j, &h)) in indices.iter().zip(hues.iter()).enumerate() {
+        let (_, path) = &shapes.glyphs[j];
+        let (r, g, b) = hsv_to_rgb(h, style.saturation, style.value);
+        let (x, y, angle, k) = placement(layout, last, i);
+        writeln!(
+            w,
+            r#"  <path paint-order="stroke" fill="rgb({r} {g} {b})" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linejoin="round" transform="translate({x} {y}) rotate({angle}) scale({k} {k})" d="{}">"#,
+            style.stroke,
+            style.stroke_opacity,
+            style.stroke_width / k,
+            path,
+        )?;
+        let values = |f: &dyn Fn((f64, f64, f64, f64)) -> String| {
+            let values: Vec<_> = frames.iter().map(|c| f(placement(layout, c, i))).collect();
+            values.join(";")
+        };
+        // each transform after the first is applied within the ones before it
+        writeln!(
+            w,
+            r#"    <animateTransform attributeName="transform" type="translate" values="{}" {timing} />"#,
+            values(&|(x, y, _, _)| format!("{x} {y}")),
+        )?;
+        writeln!(
+            w,
+            r#"    <animateTransform attributeName="transform" type="rotate" values="{}" additive="sum" {timing} />"#,
+            values(&|(_, _, angle, _)| angle.to_string()),
+        )?;
+        writeln!(
+            w,
+            r#"    <animateTransform attributeName="transform" type="scale" values="{}" additive="sum" {timing} />"#,
+            values(&|(_, _, _, k)| k.to_string()),
+        )?;
+        writeln!(
+            w,
+            r#"    <animate attributeName="stroke-width" values="{}" {timing} />"#,
+            values(&|(_, _, _, k)| (style.stroke_width / k).to_string()),
+        )?;
+        writeln!(w, "  </path>")?;
+    }
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
 fn rasterize(svg: &str, scale: f32) -> Pixmap {
     let mut tree = Tree::from_str(svg, &Options::default()).unwrap();
     tree.postprocess(PostProcessingSteps::default(), &Database::new());
@@ -402,41 +1127,242 @@ fn rasterize(svg: &str, scale: f32) -> Pixmap {
     pixmap
 }
 
-fn get_sums(dir: &Path) -> Sums {
-    let polygons: Vec<Polygon> = GLYPHS.iter().map(|&(_, path)| polygonize(path)).collect();
+/// Load the glyphs and the container that `scene` describes.
+fn load_shapes(scene: &Scene) -> Result<Shapes, String> {
+    let letters: Letters =
+        (scene.glyphs.letters.parse()).map_err(|e| format!("{:?}: {e}", scene.glyphs.letters))?;
+    let chars: String = letters.chars().iter().collect();
+    let font = scene.glyphs.font_path();
+    let size = scene.glyphs.size;
+    let glyphs = font::outlines(font, &chars, size)?;
+    let height = scene.container.height;
+    let (container, width) = match &scene.container.svg {
+        Some(source) => load_container(source, height)?,
+        None => {
+            let letter = scene.container.letter.to_string();
+            let outlines = font::outlines(font, &letter, size)?;
+            svg::outline_path(&outlines[0].1, height)?
+        }
+    };
+    Ok(Shapes {
+        letters,
+        glyphs,
+        container,
+        width,
+        height,
+        layout: scene.layout,
+        sampling: scene.sampling,
+        style: scene.style.clone(),
+    })
+}
 
-    let mut big: Vec<Point> = polygons[GLYPHS.iter().position(|&(c, _)| c == BIG).unwrap()]
-        .iter()
-        .map(|&Vec2 { x, y }| (x, y))
-        .collect();
-    big.reverse();
-    let contains: Vec<Polygon> = polygons
+/// Load the container from `source`, which is either the name of an SVG file or SVG path data,
+/// scaled to `height`.
+fn load_container(source: &str, height: f64) -> Result<(String, f64), String> {
+    let path = Path::new(source);
+    let result = if path.extension().is_some_and(|ext| ext == "svg") {
+        let text = read_to_string(path).map_err(|e| format!("{source}: {e}"))?;
+        svg::outline(&text, height)
+    } else {
+        svg::outline_path(source, height)
+    };
+    result.map_err(|e| format!("{source}: {e}"))
+}
+
+/// Return `points` scaled by `k` about the origin.
+fn scaled(k: f64, points: &[Vec2]) -> Vec<Vec2> {
+    points.iter().map(|&v| k * v).collect()
+}
+
+/// Return `polygon` scaled by `k` and rotated by `angle` radians about the origin.
+fn transformed(angle: f64, k: f64, polygon: &[Vec<Vec2>]) -> Polygon {
+    polygon
         .iter()
-        .map(|q| {
-            let q1: Vec<Point> = q
+        .map(|points| points.iter().map(|&v| rotate(angle, k * v)).collect())
+        .collect()
+}
+
+/// Return the loops of the Minkowski sum of two simple polygons, outer boundary first.
+///
+/// If `a` is clockwise then it is treated as a hole, and the result is instead the set of points
+/// where `b` can be placed without leaving that hole; this can be empty, or have several loops.
+///
+/// `extract_loops` assumes that the polygons are in general position. When they have edges pointing
+/// the same way, like two copies of the same glyph, the convolution has overlapping edges that it
+/// never splits where they meet, so it can trace the wrong loops, like a sliver or a small triangle
+/// in place of the outer boundary. That can be recognized because the result is too small: by the
+/// Brunn-Minkowski inequality, the sum of shapes with areas `A` and `B` has an area of at least
+/// `(√A + √B)²`, and when `a` is a hole, the result has no loops or some area. Whenever the result
+/// fails that check, `b` is turned by a tiny angle, which puts the edges in general position while
+/// moving its points by a negligible fraction of their distances from the origin.
+fn minkowski_sum(a: &[Vec2], b: &[Vec2]) -> Vec<Vec<Vec2>> {
+    let least = (area(a).abs().sqrt() + area(b).abs().sqrt()).powi(2);
+    let plausible = |loops: &[Vec<Vec2>]| {
+        if area(a) > 0. {
+            // the area of the sum can exceed the bound by as little as rounding error, when the
+            // polygons are convex and have the same shape
+            (loops.iter()).any(|points| area(points).abs() >= least * (1. - 1e-6))
+        } else {
+            loops.is_empty() || loops.iter().any(|points| area(points) != 0.)
+        }
+    };
+    let a: Vec<Point> = a.iter().map(|&Vec2 { x, y }| (x, y)).collect();
+    let sum = |angle: f64| -> Vec<Vec<Vec2>> {
+        let b: Vec<Point> = (b.iter())
+            .map(|&v| rotate(angle, v))
+            .map(|Vec2 { x, y }| (x, y))
+            .collect();
+        extract_loops(&reduced_convolution(&a, &b))
+            .into_iter()
+            .map(|points| {
+                points
+                    .into_iter()
+                    .map(|((x, y), _)| Vec2 { x, y })
+                    .collect()
+            })
+            .collect()
+    };
+    let mut loops = sum(0.);
+    for angle in [1e-9, 1e-7, 1e-5] {
+        if plausible(&loops) {
+            break;
+        }
+        loops = sum(angle);
+    }
+    assert!(
+        plausible(&loops),
+        "Minkowski sum should have the right area"
+    );
+    loops.retain(|points| area(points) != 0.);
+    loops.sort_by(|p, q| area(q).abs().total_cmp(&area(p).abs()));
+    loops
+}
+
+/// Return the polygon of offsets from `p` at which `q` would overlap it.
+fn overlaps(p: &Polygon, q: &Polygon) -> Polygon {
+    let mut diff = vec![minkowski_sum(&p[0], &scaled(-1., &q[0])).swap_remove(0)];
+    for hole in &p[1..] {
+        diff.extend(minkowski_sum(hole, &scaled(-1., &q[0])));
+    }
+    for hole in &q[1..] {
+        diff.extend(
+            minkowski_sum(hole, &scaled(-1., &p[0]))
                 .iter()
-                .map(|&Vec2 { x, y }| (SCALE * -x, SCALE * -y))
-                .collect();
-            extract_loops(&reduced_convolution(&big, &q1))
-                .swap_remove(0)
-                .into_iter()
-                .map(|((x, y), _)| Vec2 { x, y })
+                .map(|points| scaled(-1., points)),
+        );
+    }
+    diff
+}
+
+/// Return all the loops of each of `samples` together, for drawing them at once.
+fn merged<'a>(samples: impl IntoIterator<Item = &'a Samples>) -> Samples {
+    let mut merged: Samples = vec![];
+    for samples in samples {
+        merged.resize(samples.len(), vec![]);
+        for (row, sampled) in merged.iter_mut().zip(samples) {
+            row.resize(sampled.len(), vec![]);
+            for (loops, polygon) in row.iter_mut().zip(sampled) {
+                loops.extend(polygon.iter().cloned());
+            }
+        }
+    }
+    merged
+}
+
+/// Compute the Minkowski sums for `shapes`, writing them out as SVGs in `dir` for debugging.
+///
+/// Fails if a glyph can't fit in the container at some angle and scale that `shapes` allows.
+fn get_sums(dir: &Path, shapes: &Shapes) -> Result<Sums, String> {
+    let (layout, sampling) = (shapes.layout, shapes.sampling);
+    let flatten = Flatten {
+        tolerance: sampling.tolerance,
+        conservative: sampling.conservative,
+    };
+    let polygons: Vec<Vec<Polygon>> = shapes
+        .glyphs
+        .iter()
+        .map(|(c, path)| polygonize(path, flatten).map_err(|e| format!("glyph {c:?}: {e}")))
+        .collect::<Result<_, _>>()?;
+
+    // `m` angles and `l` scales either side of zero for each glyph, and twice that for each pair
+    let m = (layout.tilt / sampling.tilt_step).ceil() as usize;
+    let l = (layout.grow.ln() / sampling.grow_step.ln()).ceil() as usize;
+    let steps = (
+        if m > 0 {
+            layout.tilt.to_radians() / m as f64
+        } else {
+            0.
+        },
+        if l > 0 {
+            layout.grow.ln() / l as f64
+        } else {
+            0.
+        },
+    );
+    let axis = |m: usize, step: f64| (0..=2 * m).map(move |k| (k as f64 - m as f64) * step);
+    // apply `f` to `q` at each angle and scale
+    let sample = |m: usize, l: usize, q: &Polygon, f: &dyn Fn(&Polygon) -> Polygon| -> Samples {
+        axis(m, steps.0)
+            .map(|a| {
+                axis(l, steps.1)
+                    .map(|b| f(&transformed(a, b.exp(), q)))
+                    .collect()
+            })
+            .collect()
+    };
+
+    let big = polygonize(&shapes.container, flatten).map_err(|e| format!("container: {e}"))?;
+    // each piece of a glyph can only go in the pieces of the container it fits in everywhere
+    let contains: Vec<Vec<Vec<Samples>>> = (polygons.iter().zip(&shapes.glyphs))
+        .map(|(pieces, &(c, _))| {
+            (pieces.iter())
+                .map(|q| {
+                    let fits: Vec<Samples> = (big.iter())
+                        .map(|piece| {
+                            let mut outer = piece[0].clone();
+                            outer.reverse();
+                            sample(m, l, q, &|q| {
+                                minkowski_sum(&outer, &scaled(-layout.scale, &q[0]))
+                            })
+                        })
+                        .filter(|samples| samples.iter().flatten().all(|sum| !sum.is_empty()))
+                        .collect();
+                    match fits.is_empty() {
+                        true => Err(format!("the glyph {c:?} can't fit in the container")),
+                        false => Ok(fits),
+                    }
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    let holes: Vec<&Vec<Vec2>> = big.iter().flat_map(|piece| &piece[1..]).collect();
+    let excludes: Vec<Vec<Samples>> = polygons
+        .iter()
+        .map(|pieces| {
+            (pieces.iter())
+                .flat_map(|q| {
+                    holes.iter().map(move |hole| {
+                            let mut inner = hole.to_vec();
+                            inner.reverse();
+                            sample(m, l, q, &|q| {
+                                vec![minkowski_sum(&inner, &scaled(-layout.scale, &q[0]))
+                                    .swap_remove(0)]
+                            })
+                        })
+                })
                 .collect()
         })
         .collect();
 
-    let pairs: Vec<Vec<Polygon>> = polygons
+    let pairs: Vec<Vec<Vec<Samples>>> = polygons
         .iter()
         .map(|p| {
-            let p1: Vec<Point> = p.iter().map(|&Vec2 { x, y }| (x, y)).collect();
             polygons
                 .iter()
                 .map(|q| {
-                    let q1: Vec<Point> = q.iter().map(|&Vec2 { x, y }| (-x, -y)).collect();
-                    extract_loops(&reduced_convolution(&p1, &q1))
-                        .swap_remove(0)
-                        .into_iter()
-                        .map(|((x, y), _)| Vec2 { x, y })
+                    (p.iter())
+                        .flat_map(|p| q.iter().map(move |q| (p, q)))
+                        .map(|(p, q)| sample(2 * m, 2 * l, q, &|q| overlaps(p, q)))
                         .collect()
                 })
                 .collect()
@@ -446,93 +1372,1001 @@ fn get_sums(dir: &Path) -> Sums {
     let dir_polygons = dir.join("polygons");
     create_dir_all(&dir_polygons).unwrap();
     for (i, p) in polygons.iter().enumerate() {
-        let (c, _) = GLYPHS[i];
+        let (c, _) = shapes.glyphs[i];
         let mut s = String::new();
-        polygon(&mut s, p).unwrap();
+        polygon(&mut s, &p.concat()).unwrap();
         File::create(dir_polygons.join(format!("{c}.svg")))
             .unwrap()
             .write_all(s.as_bytes())
             .unwrap();
     }
 
+    // write every sample, marking transformed ones with their angle in degrees and scale factor
+    let write = |dir: &Path, name: String, m: usize, l: usize, samples: &Samples| {
+        for (a, row) in axis(m, steps.0).zip(samples) {
+            for (b, sum) in axis(l, steps.1).zip(row) {
+                let mut suffix = String::new();
+                if a != 0. {
+                    suffix += &format!("@{}", a.to_degrees().round());
+                }
+                if b != 0. {
+                    suffix += &format!("x{:.2}", b.exp());
+                }
+                let mut s = String::new();
+                polygon(&mut s, sum).unwrap();
+                File::create(dir.join(format!("{name}{suffix}.svg")))
+                    .unwrap()
+                    .write_all(s.as_bytes())
+                    .unwrap();
+            }
+        }
+    };
+
     let dir_contains = dir.join("contains");
     create_dir_all(&dir_contains).unwrap();
-    for (i, contain) in contains.iter().enumerate() {
-        let (c, _) = GLYPHS[i];
-        let mut s = String::new();
-        polygon(&mut s, contain).unwrap();
-        File::create(dir_contains.join(format!("{BIG}c-{c}.svg")))
-            .unwrap()
-            .write_all(s.as_bytes())
-            .unwrap();
+    for (i, pieces) in contains.iter().enumerate() {
+        let (c, _) = shapes.glyphs[i];
+        write(
+            &dir_contains,
+            format!("{c}"),
+            m,
+            l,
+            &merged(pieces.iter().flatten()),
+        );
+        for (k, samples) in excludes[i].iter().enumerate() {
+            write(&dir_contains, format!("{c}-{k}"), m, l, samples);
+        }
     }
 
     let dir_diffs = dir.join("diffs");
     create_dir_all(&dir_diffs).unwrap();
     for (i, diffs) in pairs.iter().enumerate() {
-        let (a, _) = GLYPHS[i];
-        for (j, diff) in diffs.iter().enumerate() {
-            let (b, _) = GLYPHS[j];
-            let mut s = String::new();
-            polygon(&mut s, diff).unwrap();
-            File::create(dir_diffs.join(format!("{a}-{b}.svg")))
-                .unwrap()
-                .write_all(s.as_bytes())
-                .unwrap();
+        let (a, _) = shapes.glyphs[i];
+        for (j, pieces) in diffs.iter().enumerate() {
+            let (b, _) = shapes.glyphs[j];
+            write(
+                &dir_diffs,
+                format!("{a}-{b}"),
+                2 * m,
+                2 * l,
+                &merged(pieces),
+            );
         }
     }
 
-    Sums { contains, pairs }
+    let radii: Vec<Vec<f64>> = pairs
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|pieces| {
+                    pieces
+                        .iter()
+                        .flatten()
+                        .flatten()
+                        .flatten()
+                        .flatten()
+                        .map(|&v| dot(v, v).sqrt())
+                        .fold(0., f64::max)
+                })
+                .collect()
+        })
+        .collect();
+    let radius = radii.iter().flatten().copied().fold(0., f64::max);
+
+    Ok(Sums {
+        steps,
+        contains,
+        excludes,
+        pairs,
+        radii,
+        radius,
+        layout,
+    })
+}
+
+/// Where to save arrangements, and how to render them.
+#[derive(Clone, Debug)]
+struct Output {
+    dir: PathBuf,
+
+    /// How many times larger than the SVGs to make the PNGs.
+    png_scale: f32,
+
+    /// How long animations last, in seconds.
+    duration: f64,
+
+    /// Which steps of each run to save.
+    capture: Capture,
 }
 
-fn run(dir: &Path, sums: &Sums, seed: u64, n: usize) -> f64 {
-    let dir_frames = dir.join(format!("{seed}-{n}"));
-    create_dir_all(&dir_frames).unwrap();
-    let scale = 10.;
-    let mut i: usize = 0;
-    let (
-        Glyphs {
+impl Output {
+    /// Return the same output, but into the subdirectory `name`, which is created if need be.
+    fn subdir(&self, name: &str) -> Output {
+        let dir = self.dir.join(name);
+        create_dir_all(&dir).unwrap();
+        Output { dir, ..*self }
+    }
+
+    /// Write the arrangement to `{name}.svg` and `{name}.png`.
+    fn save(&self, name: &str, shapes: &Shapes, indices: &[usize], hues: &[f64], coords: &[f64]) {
+        let mut s = String::new();
+        arrangement(&mut s, shapes, indices, hues, coords).unwrap();
+        File::create(self.dir.join(format!("{name}.svg")))
+            .unwrap()
+            .write_all(s.as_bytes())
+            .unwrap();
+        rasterize(&s, self.png_scale)
+            .save_png(self.dir.join(format!("{name}.png")))
+            .unwrap();
+    }
+
+    /// Write an animation through each of `frames`, given as coordinates, to `{name}.svg`.
+    fn animate(
+        &self,
+        name: &str,
+        shapes: &Shapes,
+        indices: &[usize],
+        hues: &[f64],
+        frames: &[Vec<f64>],
+    ) {
+        let mut s = String::new();
+        animation(&mut s, shapes, indices, hues, frames, self.duration).unwrap();
+        File::create(self.dir.join(format!("{name}.svg")))
+            .unwrap()
+            .write_all(s.as_bytes())
+            .unwrap();
+    }
+}
+
+/// Optimize from `start`, returning the result along with its objective value, the reason
+/// optimization stopped, and how many steps have been taken in all.
+///
+/// If `frames` is given, the arrangement is saved there after the steps its `capture` asks for and at
+/// the end, with progress printed for each, and so are checkpoints, as described for `optimize`.
+/// Frames are named by their step, padded with zeros to six digits, or as many as the last step
+/// `stop` allows, so that they sort in order. Without a step limit, they only sort in order up to
+/// step 999999. The end result is also exported there as `arrangement.json` and `arrangement.csv`,
+/// noting the `seed` it came from, and `animation.svg` moves through all the frames saved since
+/// `start`.
+fn run(
+    shapes: &Shapes,
+    sums: &Sums,
+    optimizer: &dyn Optimizer,
+    stop: Stop,
+    seed: Option<u64>,
+    start: Checkpoint,
+    frames: Option<&Output>,
+) -> (Glyphs, f64, Reason, usize) {
+    let mut i = start.steps;
+    let width = match stop.max_steps {
+        0 => 6,
+        max => (i + max).to_string().len().max(6),
+    };
+    let checkpoint = frames.map(|out| out.dir.join("checkpoint.txt"));
+    let capture = frames.map(|out| out.capture);
+    let mut recorded: Vec<Vec<f64>> = vec![];
+    // the objective reported with the last frame saved
+    let mut last = None;
+    // for `Capture::Count`, every `stride`th step so far, thinned out whenever there are too many
+    let mut kept: Vec<(usize, Option<f64>, Vec<f64>)> = vec![];
+    let mut stride = 1;
+    // for L-BFGS, how many times each kind of trouble has come up, as of the last step
+    let mut counts = None;
+    let mut save = |i: usize, fx: Option<f64>, indices: &[usize], hues: &[f64], coords: &[f64]| {
+        if let Some(out) = frames {
+            match fx {
+                Some(fx) => println!("i = {i}, fx = {fx}"),
+                None => println!("i = {i}"),
+            }
+            out.save(&format!("{i:0width$}"), shapes, indices, hues, coords);
+            recorded.push(coords.to_vec());
+        }
+    };
+    let (glyphs, fx, reason) = optimize(
+        shapes,
+        sums,
+        optimizer,
+        stop,
+        start,
+        checkpoint.as_deref(),
+        |step, indices, hues, coords| {
+            counts = step.map_or(counts, |step| step.counts);
+            let Some(capture) = capture else {
+                return;
+            };
+            let fx = match step {
+                Some(step) => {
+                    i += 1;
+                    step.fx
+                }
+                None => val_and_grad(sums, indices, coords, &mut vec![0.; coords.len()]),
+            };
+            if let Capture::Count(count) = capture {
+                if kept
+                    .first()
+                    .is_none_or(|&(first, ..)| (i - first).is_multiple_of(stride))
+                {
+                    kept.push((i, step.map(|step| step.fx), coords.to_vec()));
+                }
+                if kept.len() > 2 * count {
+                    let mut k = 0;
+                    kept.retain(|_| {
+                        k += 1;
+                        k % 2 == 1
+                    });
+                    stride *= 2;
+                }
+            } else if capture.wants(i, fx, last) {
+                save(i, step.map(|step| step.fx), indices, hues, coords);
+                last = Some(fx);
+            }
+        },
+    );
+    if let Some(out) = frames {
+        let Glyphs {
             indices,
             hues,
             coords,
-        },
-        fx,
-    ) = optimize(sums, init(seed, n), |info, indices, hues, coords| {
-        if i.count_ones() < 2 {
-            print!("i = {i}");
-            if let Some(info) = info {
-                println!(", fx = {}", info.fx);
-            } else {
-                println!();
+        } = &glyphs;
+        if let Some(&(first, ..)) = kept.first() {
+            let mut steps: Vec<usize> = (out.capture.spread(first, i).into_iter())
+                .filter(|&step| step < i)
+                .map(|step| {
+                    // the kept steps are in order, so the nearest is next to where `step` would go
+                    let k = kept.partition_point(|&(j, ..)| j < step);
+                    let near = |&k: &usize| kept[k].0.abs_diff(step);
+                    [k.saturating_sub(1), k.min(kept.len() - 1)]
+                        .into_iter()
+                        .min_by_key(near)
+                        .unwrap()
+                })
+                .collect();
+            steps.dedup();
+            for k in steps {
+                let (step, fx, coords) = &kept[k];
+                if *step < i {
+                    save(*step, *fx, indices, hues, coords);
+                }
             }
-            let mut s = String::new();
-            arrangement(&mut s, indices, hues, coords).unwrap();
-            File::create(dir_frames.join(format!("{i}.svg")))
-                .unwrap()
-                .write_all(s.as_bytes())
-                .unwrap();
-            rasterize(&s, scale)
-                .save_png(dir_frames.join(format!("{i}.png")))
-                .unwrap();
-        }
-        i += 1;
-    });
-    i -= 1;
-    println!("i = {i}, fx = {fx}");
-    let mut s = String::new();
-    arrangement(&mut s, &indices, &hues, &coords).unwrap();
-    File::create(dir_frames.join(format!("{i}.svg")))
-        .unwrap()
-        .write_all(s.as_bytes())
+        }
+        println!("i = {i}, fx = {fx}, stopped because {reason}");
+        if let Some(counts) = counts {
+            println!("L-BFGS: {counts}");
+        }
+        out.save(&format!("{i:0width$}"), shapes, indices, hues, coords);
+        if recorded.last() != Some(coords) {
+            recorded.push(coords.clone());
+        }
+        out.animate("animation", shapes, indices, hues, &recorded);
+        export(out, shapes, &glyphs, seed, fx, i);
+    }
+    (glyphs, fx, reason, i)
+}
+
+/// Export `glyphs` to `arrangement.json` and `arrangement.csv` in `out`, along with the `seed` they
+/// came from, their objective value `fx` and how many steps it took to get there.
+fn export(
+    out: &Output,
+    shapes: &Shapes,
+    glyphs: &Glyphs,
+    seed: Option<u64>,
+    fx: f64,
+    steps: usize,
+) {
+    let export = Export {
+        seed,
+        n: glyphs.indices.len(),
+        fx,
+        iterations: steps,
+        glyphs: export::placed(shapes, glyphs),
+    };
+    export.save(&out.dir, "arrangement");
+}
+
+/// Run each of the `seeds` in parallel, without saving any frames, and save and export only the
+/// best result, along with a table of how each seed did. Return the best seed.
+///
+/// An arrangement in which the glyphs fit beats one in which they don't, and otherwise the lower
+/// objective wins.
+fn multi_start(
+    out: &Output,
+    shapes: &Shapes,
+    sums: &Sums,
+    optimizer: &dyn Optimizer,
+    stop: Stop,
+    seeds: Range<u64>,
+    n: usize,
+) -> u64 {
+    let out_best = out.subdir(&format!("{}-{}-{n}", seeds.start, seeds.end - 1));
+    let results: Vec<(u64, Glyphs, f64, bool, Reason, usize)> = seeds
+        .into_par_iter()
+        .map(|seed| {
+            let start = Checkpoint::new(init(shapes, &mut Pcg64Mcg::seed_from_u64(seed), n));
+            let (glyphs, fx, reason, steps) = run(shapes, sums, optimizer, stop, None, start, None);
+            let fits = penalty(sums, &glyphs) <= FEASIBLE;
+            (seed, glyphs, fx, fits, reason, steps)
+        })
+        .collect();
+    let (best, glyphs, best_fx, _, _, steps) = results
+        .iter()
+        .min_by(|(_, _, fx1, fits1, ..), (_, _, fx2, fits2, ..)| {
+            fits2.cmp(fits1).then(fx1.total_cmp(fx2))
+        })
         .unwrap();
-    rasterize(&s, scale)
-        .save_png(dir_frames.join(format!("{i}.png")))
+    let mut table = String::new();
+    writeln!(
+        table,
+        "{:>6}  {:>20}  {:>5}  stopped because",
+        "seed", "fx", "fits"
+    )
+    .unwrap();
+    for (seed, _, fx, fits, reason, _) in &results {
+        let mark = if seed == best { " (best)" } else { "" };
+        writeln!(table, "{seed:>6}  {fx:>20.6}  {fits:>5}  {reason}{mark}").unwrap();
+    }
+    print!("{table}");
+    File::create(out_best.dir.join("summary.txt"))
+        .unwrap()
+        .write_all(table.as_bytes())
         .unwrap();
-    fx
+    let Glyphs {
+        indices,
+        hues,
+        coords,
+    } = glyphs;
+    out_best.save(&best.to_string(), shapes, indices, hues, coords);
+    export(&out_best, shapes, glyphs, Some(*best), *best_fx, *steps);
+    *best
+}
+
+/// Optimize `n` glyphs as in `run`, then alternate rounds of discrete moves with optimizing again.
+///
+/// Each move either swaps two different letters, or relocates one glyph to the spot with the most
+/// clearance from the others. A swap exchanges the angles and scales along with the positions,
+/// since those were fitted to the room around each spot rather than to the letter. Moves are
+/// accepted by simulated annealing on the objective. The arrangement after each round is saved,
+/// named by the round, and the best of them is also saved as `best` and exported.
+#[allow(clippy::too_many_arguments)]
+fn hybrid(
+    out: &Output,
+    shapes: &Shapes,
+    sums: &Sums,
+    optimizer: &dyn Optimizer,
+    stop: Stop,
+    moves: Moves,
+    seed: u64,
+    n: usize,
+) {
+    let rounds = out.subdir(&format!("{seed}-{n}-hybrid"));
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+    let mut glyphs = init(shapes, &mut rng, n);
+    let mut grad = vec![0.; 4 * n];
+    let mut objective =
+        |glyphs: &Glyphs| val_and_grad(sums, &glyphs.indices, &glyphs.coords, &mut grad);
+    // the best arrangement after any round, its objective value, and the steps taken to get there
+    let mut best: Option<(Glyphs, f64, usize)> = None;
+    let mut steps = 0;
+    let mut temperature = moves.temperature;
+    for round in 0..=moves.rounds {
+        (glyphs, _, _) = optimize(
+            shapes,
+            sums,
+            optimizer,
+            stop,
+            Checkpoint::new(glyphs),
+            None,
+            |step, _, _, _| steps += step.is_some() as usize,
+        );
+        let Glyphs {
+            indices,
+            hues,
+            coords,
+        } = &glyphs;
+        rounds.save(&round.to_string(), shapes, indices, hues, coords);
+        let mut current = objective(&glyphs);
+        println!("round = {round}, fx = {current}");
+        if best.as_ref().is_none_or(|&(_, fx, _)| current < fx) {
+            best = Some((glyphs.clone(), current, steps));
+        }
+        if round == moves.rounds {
+            break;
+        }
+        let mut accepted = 0;
+        for _ in 0..moves.tries {
+            let mut next = glyphs.clone();
+            let i = rng.gen_range(0..n);
+            if rng.gen_bool(0.5) {
+                let j = rng.gen_range(0..n);
+                if next.indices[i] == next.indices[j] {
+                    continue;
+                }
+                for k in 0..4 {
+                    next.coords.swap(k * n + i, k * n + j);
+                }
+            } else {
+                let mut others = next.clone();
+                others.remove(i);
+                let v = free_spot(shapes, sums, &others, next.indices[i], shapes.height / 100.);
+                next.coords[i] = v.x;
+                next.coords[n + i] = v.y;
+            }
+            let fx = objective(&next);
+            if fx < current || rng.gen::<f64>() < ((current - fx) / temperature).exp() {
+                glyphs = next;
+                current = fx;
+                accepted += 1;
+            }
+        }
+        println!("accepted {accepted} of {} moves", moves.tries);
+        temperature *= moves.cooling;
+    }
+    let (glyphs, fx, steps) = best.unwrap();
+    let Glyphs {
+        indices,
+        hues,
+        coords,
+    } = &glyphs;
+    rounds.save("best", shapes, indices, hues, coords);
+    export(&rounds, shapes, &glyphs, Some(seed), fx, steps);
+}
+
+/// Starting from `n` glyphs, keep adding one more and optimizing again until they no longer fit,
+/// returning the most glyphs that did fit, if any.
+///
+/// Each new glyph goes in the spot with the most clearance. The final arrangement for each count
+/// is saved, named by that count.
+fn fill(
+    out: &Output,
+    shapes: &Shapes,
+    sums: &Sums,
+    optimizer: &dyn Optimizer,
+    stop: Stop,
+    seed: u64,
+    n: usize,
+) -> Option<usize> {
+    let frames = out.subdir(&format!("{seed}-fill"));
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+    let mut glyphs = init(shapes, &mut rng, n);
+    let mut best = None;
+    loop {
+        (glyphs, _, _) = optimize(
+            shapes,
+            sums,
+            optimizer,
+            stop,
+            Checkpoint::new(glyphs),
+            None,
+            |_, _, _, _| {},
+        );
+        let Glyphs {
+            indices,
+            hues,
+            coords,
+        } = &glyphs;
+        let n = indices.len();
+        let fx = penalty(sums, &glyphs);
+        println!("n = {n}, fx = {fx}");
+        frames.save(&n.to_string(), shapes, indices, hues, coords);
+        if fx > FEASIBLE {
+            break;
+        }
+        best = Some(n);
+        let index = shapes.letters.pick(&mut rng, glyphs.indices.len());
+        let hue = rng.gen_range(0.0..360.0);
+        let v = free_spot(shapes, sums, &glyphs, index, shapes.height / 100.);
+        glyphs.push(index, hue, [v.x, v.y, 0., -shapes.layout.grow.ln()]);
+    }
+    match best {
+        Some(n) => println!("at most {n} glyphs fit"),
+        None => println!("{n} glyphs are already too many"),
+    }
+    best
+}
+
+/// Read the glyphs exported to `path` as JSON or CSV.
+fn import(path: &Path, shapes: &Shapes) -> Glyphs {
+    let placed = export::load(path).unwrap_or_else(|e| fail(e));
+    export::glyphs(shapes, &placed).unwrap_or_else(|e| fail(format!("{}: {e}", path.display())))
+}
+
+/// Read the glyphs from a checkpoint, or exported to `path` as JSON or CSV, whose indices refer to
+/// `chars`.
+fn load_glyphs(path: &Path, shapes: &Shapes, chars: &str) -> Glyphs {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json" | "csv") => import(path, shapes),
+        _ => {
+            Checkpoint::load(path, chars)
+                .unwrap_or_else(|e| fail(e))
+                .glyphs
+        }
+    }
+}
+
+/// Pack lots of small glyphs into one big one.
+#[derive(Parser)]
+struct Cli {
+    /// Where to write the results [default: out, or alongside the checkpoint for render and
+    /// --resume]
+    #[arg(long, global = true)]
+    out: Option<PathBuf>,
+
+    /// A TOML file describing the container, the glyphs, how they look, and how they're optimized
+    #[arg(long, global = true)]
+    scene: Option<PathBuf>,
+
+    /// Which letters to pack, like AGHIPRS, "S:3 P:1.5 R:1", "12S 10P 8R" or '"HELLO WORLD"'
+    /// [default: from the scene, or else AGHIPRS]
+    #[arg(long, global = true)]
+    letters: Option<String>,
+
+    /// The container to pack them into, as an SVG file or SVG path data [default: from the scene,
+    /// or else the letter S]
+    #[arg(long, global = true)]
+    container: Option<String>,
+
+    /// How many times larger than the SVGs to make the PNGs
+    #[arg(long, global = true, default_value_t = 10.)]
+    png_scale: f32,
+
+    /// How many seconds the animation of each run lasts
+    #[arg(long, global = true, default_value_t = 10.)]
+    duration: f64,
+
+    /// Which steps of each run to save as frames, besides the last: every:K, log for the first and
+    /// each power of two, log:K for K per doubling, drops:FRACTION whenever the objective drops by
+    /// that fraction, count:K for K spread evenly, or final
+    #[arg(long, global = true, default_value = "log")]
+    frames: Capture,
+
+    /// What to do [default: run]
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compute the Minkowski sums, writing them out as SVGs for debugging
+    Sums,
+
+    /// Optimize an arrangement of the glyphs, which is what happens without a command
+    Run(RunArgs),
+
+    /// Render the arrangement in a checkpoint or export to SVG and PNG files
+    Render {
+        /// The checkpoint, or the arrangement exported as .json or .csv, as saved by run
+        checkpoint: PathBuf,
+    },
+
+    /// Check that an arrangement's glyphs are inside the container and don't overlap, using finely
+    /// flattened outlines, and exit with an error if not
+    Verify {
+        /// The checkpoint, or the arrangement exported as .json or .csv, as saved by run
+        arrangement: PathBuf,
+
+        /// The maximum distance between an outline and the polygon approximating it
+        #[arg(long, default_value_t = 0.01)]
+        tolerance: f64,
+    },
+}
+
+// only one way of running can be chosen at a time
+#[derive(Parser)]
+#[command(group(ArgGroup::new("mode").args(["resume", "start", "fill", "moves", "starts"])))]
+struct RunArgs {
+    /// The seed for the random starting arrangement, or the first of them with --starts
+    #[arg(long, default_value_t = 2532)]
+    seed: u64,
+
+    /// How many glyphs to pack, or to start from with --fill [default: as many as the letters call
+    /// for, or else 80, or 40 with --fill]
+    #[arg(short)]
+    n: Option<usize>,
+
+    /// How many seeds to try, keeping only the best result
+    #[arg(long, default_value_t = 1)]
+    starts: u64,
+
+    /// Find how many glyphs fit, rather than optimizing a fixed number of them
+    #[arg(long)]
+    fill: bool,
+
+    /// How many rounds of discrete moves to interleave with optimizing, which swap glyphs or
+    /// relocate them to free spots [default: from the scene, or else none]
+    #[arg(long)]
+    moves: Option<usize>,
+
+    /// Which optimizer to use: lbfgs, momentum, adam or cg [default: from the scene, or else lbfgs]
+    #[arg(long, env = "OPTIMIZER")]
+    optimizer: Option<String>,
+
+    /// Carry on from a checkpoint instead, putting the frames alongside it
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Start from an arrangement exported as .json or .csv instead of a random one
+    #[arg(long)]
+    start: Option<PathBuf>,
+
+    /// Stop once a step changes the objective by at most this fraction of it [default: from the
+    /// scene, or else 1e-9]
+    #[arg(long)]
+    rel_change: Option<f64>,
+
+    /// Stop once the norm of the gradient is at most this [default: from the scene, or else 0]
+    #[arg(long)]
+    grad_norm: Option<f64>,
+
+    /// Stop after this many steps, or never with 0 [default: from the scene, or else 10000]
+    #[arg(long)]
+    max_steps: Option<usize>,
+
+    /// Stop once this many seconds have passed, or never with 0 [default: from the scene, or else
+    /// 0]
+    #[arg(long)]
+    time: Option<f64>,
+
+    /// Stop once the objective, not counting the reward for growth, is at most this [default: from
+    /// the scene, or else 0 unless growth is rewarded]
+    #[arg(long)]
+    penalty: Option<f64>,
+
+    /// Don't stop for the penalty, whatever the scene says
+    #[arg(long, conflicts_with = "penalty")]
+    no_penalty: bool,
+}
+
+/// Print `message` as an error and exit, for problems with what the program was asked to do.
+fn fail(message: impl fmt::Display) -> ! {
+    eprintln!("error: {message}");
+    exit(1)
 }
 
 fn main() {
-    let dir = Path::new("out");
-    let sums = get_sums(dir);
-    run(dir, &sums, 2532, 80);
+    let cli = Cli::parse();
+    let mut scene = match &cli.scene {
+        Some(path) => Scene::load(path).unwrap_or_else(|e| fail(e)),
+        None => Scene::default(),
+    };
+    if let Some(letters) = cli.letters {
+        scene.glyphs.letters = letters;
+    }
+    if let Some(source) = cli.container {
+        scene.container.svg = Some(source);
+    }
+    let shapes = load_shapes(&scene).unwrap_or_else(|e| fail(e));
+    let chars: String = shapes.letters.chars().iter().collect();
+    let n = shapes.letters.count();
+    let out = Output {
+        dir: cli.out.clone().unwrap_or_else(|| PathBuf::from("out")),
+        png_scale: cli.png_scale,
+        duration: cli.duration,
+        capture: cli.frames,
+    };
+    let command = cli
+        .command
+        .unwrap_or_else(|| Command::Run(RunArgs::parse_from(["run"])));
+    let args = match command {
+        Command::Sums => {
+            get_sums(&out.dir, &shapes).unwrap_or_else(|e| fail(e));
+            return;
+        }
+        Command::Render { checkpoint } => {
+            let glyphs = load_glyphs(&checkpoint, &shapes, &chars);
+            let Glyphs {
+                indices,
+                hues,
+                coords,
+            } = &glyphs;
+            let out = Output {
+                dir: cli
+                    .out
+                    .unwrap_or_else(|| checkpoint.parent().unwrap().to_owned()),
+                ..out
+            };
+            create_dir_all(&out.dir).unwrap();
+            let name = checkpoint.file_stem().unwrap().to_string_lossy();
+            out.save(&name, &shapes, indices, hues, coords);
+            return;
+        }
+        Command::Verify {
+            arrangement,
+            tolerance,
+        } => {
+            if !(tolerance > 0. && tolerance.is_finite()) {
+                fail(format!(
+                    "--tolerance should be positive and finite, not {tolerance}"
+                ));
+            }
+            let glyphs = load_glyphs(&arrangement, &shapes, &chars);
+            let report = verify::verify(&shapes, &glyphs, tolerance).unwrap_or_else(|e| fail(e));
+            println!("{report}");
+            if !report.passed() {
+                exit(1);
+            }
+            return;
+        }
+        Command::Run(args) => args,
+    };
+    if let Some(name) = args.optimizer {
+        scene.optimizer.name = name;
+    }
+    let stop = &mut scene.stop;
+    stop.grad_norm = args.grad_norm.unwrap_or(stop.grad_norm);
+    stop.rel_change = args.rel_change.unwrap_or(stop.rel_change);
+    stop.max_steps = args.max_steps.unwrap_or(stop.max_steps);
+    stop.time = args.time.unwrap_or(stop.time);
+    if let Some(penalty) = args.penalty {
+        stop.penalty = Penalty::At(penalty);
+    }
+    if args.no_penalty {
+        stop.penalty = Penalty::Off;
+    }
+    stop.check().unwrap_or_else(|e| fail(e));
+    scene.moves.rounds = args.moves.unwrap_or(scene.moves.rounds);
+    let other = args.resume.is_some() || args.start.is_some() || args.fill || args.starts > 1;
+    if scene.moves.rounds > 0 && other {
+        fail("the scene's moves can't be combined with --resume, --start, --fill or --starts");
+    }
+    let optimizer = get_optimizer(&scene.optimizer).unwrap_or_else(|e| fail(e));
+    let stop = scene.stop;
+    let sums = get_sums(&out.dir, &shapes).unwrap_or_else(|e| fail(e));
+    let n = args.n.or(n);
+    if let Some(path) = &args.resume {
+        // carry on where an earlier run left off, putting the frames alongside its checkpoint unless
+        // told otherwise
+        let start = Checkpoint::load(path, &chars).unwrap_or_else(|e| fail(e));
+        let name = &scene.optimizer.name;
+        let saved = start
+            .state
+            .as_ref()
+            .map_or(name.as_str(), |state| state.name());
+        if saved != name {
+            fail(format!(
+                "{} was saved by {saved}, not {name}",
+                path.display()
+            ));
+        }
+        let frames = Output {
+            dir: cli.out.unwrap_or_else(|| path.parent().unwrap().to_owned()),
+            ..out
+        };
+        create_dir_all(&frames.dir).unwrap();
+        run(
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            None,
+            start,
+            Some(&frames),
+        );
+    } else if let Some(path) = &args.start {
+        let glyphs = import(path, &shapes);
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let frames = out.subdir(&format!("{name}-{}", glyphs.indices.len()));
+        let start = Checkpoint::new(glyphs);
+        run(
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            None,
+            start,
+            Some(&frames),
+        );
+    } else if args.fill {
+        fill(
+            &out,
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            args.seed,
+            n.unwrap_or(40),
+        );
+    } else if scene.moves.rounds > 0 {
+        hybrid(
+            &out,
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            scene.moves,
+            args.seed,
+            n.unwrap_or(80),
+        );
+    } else if args.starts > 1 {
+        let seeds = args.seed..args.seed + args.starts;
+        multi_start(
+            &out,
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            seeds,
+            n.unwrap_or(80),
+        );
+    } else {
+        let n = n.unwrap_or(80);
+        let frames = out.subdir(&format!("{}-{n}", args.seed));
+        let glyphs = init(&shapes, &mut Pcg64Mcg::seed_from_u64(args.seed), n);
+        run(
+            &shapes,
+            &sums,
+            &*optimizer,
+            stop,
+            Some(args.seed),
+            Checkpoint::new(glyphs),
+            Some(&frames),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLATTEN: Flatten = Flatten {
+        tolerance: 0.01,
+        conservative: false,
+    };
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Vec2> {
+        coords.iter().map(|&(x, y)| vec2(x, y)).collect()
+    }
+
+    #[test]
+    fn relative_lines() {
+        let square = points(&[(1., 2.), (11., 2.), (11., 12.), (1., 12.)]);
+        assert_eq!(
+            polygonize("m 1 2 l 10 0 v 10 h -10 z", FLATTEN).unwrap(),
+            [[square.clone()]]
+        );
+        assert_eq!(
+            polygonize("M 1 2 H 11 V 12 L 1 12 Z", FLATTEN).unwrap(),
+            [[square.clone()]]
+        );
+    }
+
+    #[test]
+    fn smooth_quadratics() {
+        let explicit = polygonize(
+            "M 0 0 Q 10 20 20 0 Q 30 -20 40 0 L 40 -10 L 0 -10 Z",
+            FLATTEN,
+        )
+        .unwrap();
+        let smooth = polygonize("M 0 0 Q 10 20 20 0 T 40 0 L 40 -10 L 0 -10 Z", FLATTEN).unwrap();
+        let relative = polygonize("m 0 0 q 10 20 20 0 t 20 0 v -10 h -40 z", FLATTEN).unwrap();
+        assert!(explicit[0][0].len() > 10);
+        assert_eq!(smooth, explicit);
+        assert_eq!(relative, explicit);
+    }
+
+    #[test]
+    fn smooth_cubics() {
+        let explicit =
+            polygonize("M 0 0 C 0 20 20 20 20 0 C 20 -20 40 -20 40 0 Z", FLATTEN).unwrap();
+        let smooth = polygonize("M 0 0 C 0 20 20 20 20 0 S 40 -20 40 0 Z", FLATTEN).unwrap();
+        let relative = polygonize("m 0 0 c 0 20 20 20 20 0 s 20 -20 20 0 z", FLATTEN).unwrap();
+        assert!(explicit[0][0].len() > 10);
+        assert_eq!(smooth, explicit);
+        assert_eq!(relative, explicit);
+    }
+
+    #[test]
+    fn arcs() {
+        let circle =
+            polygonize("M 0 10 A 10 10 0 0 1 20 10 A 10 10 0 0 1 0 10 Z", FLATTEN).unwrap();
+        let relative =
+            polygonize("m 0 10 a 10 10 0 0 1 20 0 a 10 10 0 0 1 -20 0 z", FLATTEN).unwrap();
+        let [polygon] = &circle[..] else {
+            panic!("a circle should be one piece");
+        };
+        let [points] = &polygon[..] else {
+            panic!("a circle should have no holes");
+        };
+        // arcs are approximated by cubics, which stray from the circle by about 0.03% of its radius
+        for &p in points {
+            let d = p - vec2(10., 10.);
+            let r = dot(d, d).sqrt();
+            assert!((r - 10.).abs() < 0.005, "{p:?} should be on the circle");
+        }
+        // the relative arc is computed from a slightly different center, so it's only close
+        let other = &relative[0][0];
+        assert_eq!(other.len(), points.len());
+        for (&p, &q) in points.iter().zip(other) {
+            assert!(dot(p - q, p - q) < 1e-18, "{p:?} and {q:?} should match");
+        }
+        let gap = 100. * std::f64::consts::PI - area(points);
+        assert!((0. ..1.).contains(&gap), "{gap} should be small");
+    }
+
+    #[test]
+    fn implicit_close() {
+        let open = polygonize("M 0 0 H 10 V 10 H 0", FLATTEN).unwrap();
+        let closed = polygonize("M 0 0 H 10 V 10 H 0 Z", FLATTEN).unwrap();
+        let repeated = polygonize("M 0 0 H 10 V 10 H 0 V 0", FLATTEN).unwrap();
+        assert_eq!(open, closed);
+        assert_eq!(repeated, closed);
+    }
+
+    #[test]
+    fn continues_after_close() {
+        // after Z, the next subpath starts where the last one did
+        let path = "M 5 10 L 0 0 L 10 0 Z l 5 10 h -10 z";
+        let below = points(&[(5., 10.), (0., 0.), (10., 0.)]);
+        let above = points(&[(5., 10.), (10., 20.), (0., 20.)]);
+        assert_eq!(polygonize(path, FLATTEN).unwrap(), [[below], [above]]);
+    }
+
+    #[test]
+    fn encloses_nothing() {
+        for path in ["", "M 0 0 H 10", "M 0 0 L 10 10"] {
+            assert!(polygonize(path, FLATTEN).is_err(), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_tolerance() {
+        for tolerance in [f64::NAN, -1., 0.] {
+            let flatten = Flatten {
+                tolerance,
+                conservative: false,
+            };
+            let polygon = polygonize("M 0 0 C 0 20 20 20 20 0 Z", flatten).unwrap();
+            // every piece of the curve starts a point, and then there's its end
+            assert_eq!(polygon[0][0].len(), (1 << MAX_DEPTH) + 1);
+        }
+    }
+
+    #[test]
+    fn near_pairs_complete() {
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        for d in [0.5, 3., 100.] {
+            // some points land exactly on cell boundaries, and some have negative coordinates
+            let x: Vec<f64> = (0..300)
+                .map(|_| rng.gen_range(-10..10) as f64 / 2.)
+                .collect();
+            let y: Vec<f64> = (0..300).map(|_| rng.gen_range(-10.0..10.0)).collect();
+            let pairs = near_pairs(&x, &y, d);
+            assert!(pairs.windows(2).all(|w| w[0] < w[1]));
+            assert!(pairs.iter().all(|&(i, j)| i < j));
+            for i in 0..x.len() {
+                for j in i + 1..x.len() {
+                    let (dx, dy) = (x[j] - x[i], y[j] - y[i]);
+                    if dx * dx + dy * dy <= d * d {
+                        assert!(
+                            pairs.binary_search(&(i, j)).is_ok(),
+                            "({i}, {j}) within {d}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn val_and_grad_threads() {
+        let scene = Scene::default();
+        let shapes = load_shapes(&scene).unwrap();
+        let dir = std::env::temp_dir().join(format!("sums-{}", std::process::id()));
+        let sums = get_sums(&dir, &shapes).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        // enough glyphs that plenty of them overlap
+        let glyphs = init(&shapes, &mut Pcg64Mcg::seed_from_u64(0), 300);
+        let evaluate = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build();
+            let mut grad = vec![0.; glyphs.coords.len()];
+            let fx = pool
+                .unwrap()
+                .install(|| val_and_grad(&sums, &glyphs.indices, &glyphs.coords, &mut grad));
+            assert!(fx > 0.);
+            let bits = grad.iter().map(|g| g.to_bits());
+            [fx.to_bits()].into_iter().chain(bits).collect::<Vec<_>>()
+        };
+        let serial = evaluate(1);
+        for threads in [2, 3, 8] {
+            assert_eq!(evaluate(threads), serial, "{threads} threads");
+        }
+    }
 }
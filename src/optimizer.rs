@@ -0,0 +1,438 @@
+//! A common interface to a few ways of minimizing an objective function given its gradient, so that
+//! they can be compared on the same layouts.
+//!
+//! The objective functions here take the same form as described in the `lbfgs` module.
+
+use crate::lbfgs::{self, dot, project, Bounds};
+use serde::Deserialize;
+
+/// What an `Optimizer` reports after each step.
+#[derive(Clone, Copy)]
+pub struct Step<'a> {
+    /// The objective value at the point before this step.
+    pub fx: f64,
+
+    /// The gradient at the point before this step.
+    pub grad: &'a [f64],
+
+    /// The point before this step.
+    pub x0: &'a [f64],
+
+    /// The point after this step.
+    pub x: &'a [f64],
+
+    /// Whether the step is trustworthy: false if a line search failed to find a good step size, in
+    /// which case the optimizer starts over from steepest descent.
+    pub found: bool,
+
+    /// For L-BFGS, how many times each kind of trouble has come up so far.
+    pub counts: Option<lbfgs::Counts>,
+
+    /// Return everything the optimizer needs to carry on from `x` with `Optimizer::resume`.
+    pub state: &'a dyn Fn() -> State,
+}
+
+/// What an optimizer remembers from one step to the next, besides the current point.
+#[derive(Clone, Debug)]
+pub enum State {
+    Lbfgs(lbfgs::State),
+
+    /// The last step taken.
+    Momentum {
+        v: Vec<f64>,
+    },
+
+    /// The running averages of the gradient and squared gradient, and the decay rates raised to
+    /// the number of steps so far.
+    Adam {
+        m: Vec<f64>,
+        v: Vec<f64>,
+        beta1_t: f64,
+        beta2_t: f64,
+    },
+
+    /// The gradient at the last point, the negated search direction from there, the slope along it
+    /// and the step size relative to it if nonzero, and whether the line search succeeded.
+    Cg {
+        prev: Vec<f64>,
+        d: Vec<f64>,
+        slope_prev: f64,
+        alpha: f64,
+        found: bool,
+    },
+}
+
+impl State {
+    /// Return the name of the optimizer this state is for, as in a scene.
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Lbfgs(_) => "lbfgs",
+            State::Momentum { .. } => "momentum",
+            State::Adam { .. } => "adam",
+            State::Cg { .. } => "cg",
+        }
+    }
+}
+
+pub trait Optimizer: Sync {
+    /// Minimize `f` starting from `x`, keeping each variable within `bounds` if given, and calling
+    /// `stop` after each step until it returns true.
+    fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        stop: &mut dyn FnMut(Step) -> bool,
+    );
+
+    /// Like `minimize`, but carrying on from `x` with `state` as reported by an earlier `Step`, so
+    /// that the steps are the same as they would have been without stopping. Panics if `state` is
+    /// for a different optimizer.
+    fn resume(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        state: State,
+        stop: &mut dyn FnMut(Step) -> bool,
+    );
+}
+
+/// Panic because `state` is for another optimizer than `name`.
+fn mismatch(name: &str, state: &State) -> ! {
+    panic!("can't resume {name} from the state of {}", state.name())
+}
+
+/// L-BFGS, or L-BFGS-B when there are bounds.
+pub struct Lbfgs(pub lbfgs::Config);
+
+impl Optimizer for Lbfgs {
+    fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let state = match bounds {
+            Some(bounds) => lbfgs::first_step_bounded(self.0, &mut *f, bounds, x),
+            None => lbfgs::first_step(self.0, &mut *f, x),
+        };
+        self.resume(f, bounds, x, State::Lbfgs(state), stop);
+    }
+
+    fn resume(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        state: State,
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let State::Lbfgs(mut state) = state else {
+            mismatch("lbfgs", &state);
+        };
+        let stop = |info: lbfgs::Info| {
+            stop(Step {
+                fx: info.fx,
+                grad: &info.state.grad,
+                x0: &info.state.x,
+                x: info.x,
+                found: info.found,
+                counts: Some(info.state.counts),
+                state: &|| State::Lbfgs(info.state.clone()),
+            })
+            .then_some(())
+        };
+        match bounds {
+            Some(bounds) => lbfgs::step_until_bounded(self.0, f, bounds, x, &mut state, stop),
+            None => lbfgs::step_until(self.0, f, x, &mut state, stop),
+        }
+    }
+}
+
+/// Gradient descent with momentum: each step adds `momentum` times the last step to `rate` times
+/// the negative gradient.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Momentum {
+    pub rate: f64,
+    pub momentum: f64,
+}
+
+impl Default for Momentum {
+    fn default() -> Self {
+        Self {
+            rate: 1e-3,
+            momentum: 0.9,
+        }
+    }
+}
+
+impl Optimizer for Momentum {
+    fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let v = vec![0.; x.len()];
+        self.resume(f, bounds, x, State::Momentum { v }, stop);
+    }
+
+    fn resume(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        state: State,
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let State::Momentum { mut v } = state else {
+            mismatch("momentum", &state);
+        };
+        let n = x.len();
+        let mut grad = vec![0.; n];
+        let mut x0 = vec![0.; n];
+        loop {
+            let fx = f(x, &mut grad);
+            x0.copy_from_slice(x);
+            for i in 0..n {
+                v[i] = self.momentum * v[i] - self.rate * grad[i];
+                x[i] += v[i];
+            }
+            if let Some(bounds) = bounds {
+                project(x, bounds);
+            }
+            let step = Step {
+                fx,
+                grad: &grad,
+                x0: &x0,
+                x,
+                found: true,
+                counts: None,
+                state: &|| State::Momentum { v: v.clone() },
+            };
+            if stop(step) {
+                return;
+            }
+        }
+    }
+}
+
+/// Adam, from _Adam: A Method for Stochastic Optimization_ by Kingma and Ba, 2015, which scales
+/// each variable's step by running averages of its gradient and squared gradient.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Adam {
+    /// The largest step any one variable takes, roughly.
+    pub rate: f64,
+
+    /// The decay rate of the average gradient.
+    pub beta1: f64,
+
+    /// The decay rate of the average squared gradient.
+    pub beta2: f64,
+
+    /// A small positive constant to add to a denominator that might be zero.
+    pub eps: f64,
+}
+
+impl Default for Adam {
+    fn default() -> Self {
+        Self {
+            rate: 0.1,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+        }
+    }
+}
+
+impl Optimizer for Adam {
+    fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let n = x.len();
+        let state = State::Adam {
+            m: vec![0.; n],
+            v: vec![0.; n],
+            beta1_t: 1.,
+            beta2_t: 1.,
+        };
+        self.resume(f, bounds, x, state, stop);
+    }
+
+    fn resume(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        state: State,
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let State::Adam {
+            mut m,
+            mut v,
+            mut beta1_t,
+            mut beta2_t,
+        } = state
+        else {
+            mismatch("adam", &state);
+        };
+        let n = x.len();
+        let mut grad = vec![0.; n];
+        let mut x0 = vec![0.; n];
+        loop {
+            let fx = f(x, &mut grad);
+            x0.copy_from_slice(x);
+            beta1_t *= self.beta1;
+            beta2_t *= self.beta2;
+            for i in 0..n {
+                m[i] = self.beta1 * m[i] + (1. - self.beta1) * grad[i];
+                v[i] = self.beta2 * v[i] + (1. - self.beta2) * grad[i] * grad[i];
+                let m_hat = m[i] / (1. - beta1_t);
+                let v_hat = v[i] / (1. - beta2_t);
+                x[i] -= self.rate * m_hat / (v_hat.sqrt() + self.eps);
+            }
+            if let Some(bounds) = bounds {
+                project(x, bounds);
+            }
+            let step = Step {
+                fx,
+                grad: &grad,
+                x0: &x0,
+                x,
+                found: true,
+                counts: None,
+                state: &|| State::Adam {
+                    m: m.clone(),
+                    v: v.clone(),
+                    beta1_t,
+                    beta2_t,
+                },
+            };
+            if stop(step) {
+                return;
+            }
+        }
+    }
+}
+
+/// Nonlinear conjugate gradient with the Polak–Ribière formula, clamped to be nonnegative so that
+/// it restarts from steepest descent when progress stalls. See page 122 of Nocedal and Wright.
+///
+/// The step sizes come from the line search configured as for L-BFGS, except that the Wolfe
+/// constant should be smaller, so that each step gets closer to the minimum along its direction.
+pub struct ConjugateGradient(pub lbfgs::Config);
+
+impl Optimizer for ConjugateGradient {
+    fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        let n = x.len();
+        if let Some(bounds) = bounds {
+            project(x, bounds);
+        }
+        let state = State::Cg {
+            prev: vec![0.; n],
+            d: vec![0.; n],
+            slope_prev: 0.,
+            alpha: 0.,
+            found: false,
+        };
+        self.resume(f, bounds, x, state, stop);
+    }
+
+    fn resume(
+        &self,
+        f: &mut dyn FnMut(&[f64], &mut [f64]) -> f64,
+        bounds: Option<Bounds>,
+        x: &mut [f64],
+        state: State,
+        stop: &mut dyn FnMut(Step) -> bool,
+    ) {
+        // `d` is the negated search direction itself, and `alpha` the last nonzero step size
+        // relative to it
+        let State::Cg {
+            mut prev,
+            mut d,
+            mut slope_prev,
+            mut alpha,
+            mut found,
+        } = state
+        else {
+            mismatch("cg", &state);
+        };
+        let n = x.len();
+        let mut grad = vec![0.; n];
+        let mut scratch = vec![0.; n];
+        // the negated search direction, scaled by the initial step size
+        let mut r = vec![0.; n];
+        let mut x0 = vec![0.; n];
+        loop {
+            let fx = f(x, &mut grad);
+            let gg_prev = dot(&prev, &prev);
+            let beta = if found && gg_prev > 0. {
+                let diff: f64 = (0..n).map(|i| grad[i] * (grad[i] - prev[i])).sum();
+                (diff / gg_prev).max(0.)
+            } else {
+                0.
+            };
+            for i in 0..n {
+                d[i] = grad[i] + beta * d[i];
+            }
+            let mut slope = dot(&grad, &d);
+            if slope.is_nan() || slope <= 0. {
+                // not a descent direction, so restart
+                d.copy_from_slice(&grad);
+                slope = dot(&grad, &d);
+            }
+            // see page 59 of Nocedal and Wright
+            let guess = if alpha > 0. && slope > 0. {
+                alpha * slope_prev / slope
+            } else {
+                1.
+            };
+            for i in 0..n {
+                r[i] = guess * d[i];
+            }
+            prev.copy_from_slice(&grad);
+            x0.copy_from_slice(x);
+            scratch.copy_from_slice(&grad);
+            let t;
+            (t, found) = lbfgs::line_search(self.0, &mut *f, bounds, &x0, &r, fx, &mut scratch, x);
+            if t > 0. {
+                alpha = t * guess;
+                slope_prev = slope;
+            }
+            let step = Step {
+                fx,
+                grad: &prev,
+                x0: &x0,
+                x,
+                found,
+                counts: None,
+                state: &|| State::Cg {
+                    prev: prev.clone(),
+                    d: d.clone(),
+                    slope_prev,
+                    alpha,
+                    found,
+                },
+            };
+            if stop(step) {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,622 @@
+//! A whole design in one place: the container, the glyphs to pack into it, how they look, and how
+//! they're optimized, so that a design can be kept under version control and reproduced exactly.
+//!
+//! Scenes are written in TOML, with one table for each section below. Every key is optional,
+//! defaulting to the value given by that section's `Default`, but unknown keys are errors, so that a
+//! typo can't silently leave a setting at its default.
+
+use crate::{lbfgs, optimizer};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{fs::read_to_string, path::Path, str::FromStr};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    pub container: Container,
+    pub glyphs: Glyphs,
+    pub layout: Layout,
+    pub sampling: Sampling,
+    pub style: Style,
+    pub optimizer: Optimizer,
+    pub stop: Stop,
+    pub moves: Moves,
+}
+
+/// The big shape to pack the glyphs into.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Container {
+    /// The letter to use as the container, in the same font as the glyphs.
+    pub letter: char,
+
+    /// An SVG file or SVG path data to use instead of `letter`. In a scene file, a file is relative
+    /// to the scene's directory.
+    pub svg: Option<String>,
+
+    /// The height of the container's bounding box, which it's scaled to fit.
+    pub height: f64,
+}
+
+impl Default for Container {
+    fn default() -> Self {
+        Self {
+            letter: 'S',
+            svg: None,
+            height: 196.,
+        }
+    }
+}
+
+/// The small glyphs to pack.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Glyphs {
+    /// The font file to take the glyphs, and the container's letter, from. In a scene file, this is
+    /// relative to the scene's directory. Without one, the font that comes with the crate is used.
+    pub font: Option<String>,
+
+    /// The font size at which outlines are loaded.
+    pub size: f64,
+
+    /// Which letters to pack, in any of the forms described for `Letters`.
+    pub letters: String,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Self {
+            font: None,
+            size: 270.,
+            letters: "AGHIPRS".to_owned(),
+        }
+    }
+}
+
+impl Glyphs {
+    /// Return the font file to use, which is the one that comes with the crate unless the scene
+    /// names another.
+    pub fn font_path(&self) -> &Path {
+        let bundled = concat!(env!("CARGO_MANIFEST_DIR"), "/fonts/DejaVuSans-Bold.ttf");
+        Path::new(self.font.as_deref().unwrap_or(bundled))
+    }
+}
+
+/// How the glyphs may be placed.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    /// The size of each glyph relative to its outline at the font size.
+    pub scale: f64,
+
+    /// How far each glyph must stay from the others and from the container's edge.
+    pub gap: f64,
+
+    /// The most that a glyph may be rotated either way, in degrees.
+    pub tilt: f64,
+
+    /// The most that a glyph may be scaled up or down from `scale`, as a factor.
+    pub grow: f64,
+
+    /// How much the objective rewards each glyph for its area, relative to its area at `scale`.
+    pub growth: f64,
+
+    /// Whether to keep each glyph's position within the container's bounding box, and its angle and
+    /// scale within `tilt` and `grow`, as hard bounds rather than penalties.
+    pub bounded: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            scale: 1. / 15.,
+            gap: 3.,
+            tilt: 0.,
+            grow: 1.,
+            growth: 1.,
+            bounded: false,
+        }
+    }
+}
+
+impl Layout {
+    /// Return the angle in radians at which a glyph is drawn, given its angle coordinate.
+    ///
+    /// Coordinates beyond `tilt` are allowed, and penalized, but the glyph stops turning there.
+    pub fn tilt(&self, angle: f64) -> f64 {
+        let max = self.tilt.to_radians();
+        angle.clamp(-max, max)
+    }
+
+    /// Return the natural log of the factor by which a glyph is scaled from `scale`, given its
+    /// scale coordinate.
+    ///
+    /// Like `tilt`, this clamps the coordinate to the allowed range given by `grow`.
+    pub fn grow(&self, scale: f64) -> f64 {
+        let max = self.grow.ln();
+        scale.clamp(-max, max)
+    }
+}
+
+/// How finely shapes are approximated.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sampling {
+    /// The largest angle between the orientations for which Minkowski sums are precomputed, in
+    /// degrees.
+    pub tilt_step: f64,
+
+    /// The largest factor between the scales for which Minkowski sums are precomputed.
+    pub grow_step: f64,
+
+    /// The maximum distance between a curve and the polygon edges approximating it.
+    pub tolerance: f64,
+
+    /// Whether to push polygon edges outward so that each polygon contains the true shape.
+    pub conservative: bool,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            tilt_step: 5.,
+            grow_step: 1.1,
+            tolerance: 0.5,
+            conservative: false,
+        }
+    }
+}
+
+/// How arrangements are drawn. Each glyph is filled with a color of its own hue.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    /// The fill color of the container, in any form SVG accepts.
+    pub container: String,
+
+    /// The saturation of each glyph's fill color, from 0 to 100.
+    pub saturation: f64,
+
+    /// The value of each glyph's fill color, from 0 to 100.
+    pub value: f64,
+
+    /// The color of each glyph's outline, in any form SVG accepts.
+    pub stroke: String,
+
+    /// The opacity of each glyph's outline, from 0 to 1.
+    pub stroke_opacity: f64,
+
+    /// The width of each glyph's outline, in the same units as the container's height.
+    pub stroke_width: f64,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            container: "#C1C1C1".to_owned(),
+            saturation: 60.,
+            value: 100.,
+            stroke: "#080664".to_owned(),
+            stroke_opacity: 0xea as f64 / 255.,
+            stroke_width: 1.5,
+        }
+    }
+}
+
+/// Which optimizer to use, and the settings for each of them.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Optimizer {
+    /// `lbfgs`, `momentum` for gradient descent with momentum, `adam`, or `cg` for nonlinear
+    /// conjugate gradient.
+    pub name: String,
+
+    pub lbfgs: Lbfgs,
+    pub cg: Cg,
+    pub momentum: optimizer::Momentum,
+    pub adam: optimizer::Adam,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self {
+            name: "lbfgs".to_owned(),
+            lbfgs: Lbfgs::default(),
+            cg: Cg::default(),
+            momentum: optimizer::Momentum::default(),
+            adam: optimizer::Adam::default(),
+        }
+    }
+}
+
+/// The fields of `lbfgs::Config`, which are also used for the line searches of `cg`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lbfgs {
+    pub m: usize,
+    pub armijo: f64,
+    pub wolfe: f64,
+    pub min_interval: f64,
+    pub max_steps: usize,
+    pub search: lbfgs::Search,
+    pub curvature: lbfgs::Curvature,
+    pub epsd: f64,
+}
+
+impl Default for Lbfgs {
+    fn default() -> Self {
+        Self {
+            m: 17,
+            armijo: 0.001,
+            wolfe: 0.9,
+            min_interval: 1e-9,
+            max_steps: 10,
+            search: lbfgs::Search::Bisection,
+            curvature: lbfgs::Curvature::Damp,
+            epsd: 1e-11,
+        }
+    }
+}
+
+impl From<Lbfgs> for lbfgs::Config {
+    fn from(cfg: Lbfgs) -> Self {
+        Self {
+            m: cfg.m,
+            armijo: cfg.armijo,
+            wolfe: cfg.wolfe,
+            min_interval: cfg.min_interval,
+            max_steps: cfg.max_steps,
+            search: cfg.search,
+            curvature: cfg.curvature,
+            epsd: cfg.epsd,
+        }
+    }
+}
+
+/// The line search settings for `cg` that differ from those for `lbfgs`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cg {
+    pub wolfe: f64,
+    pub max_steps: usize,
+}
+
+impl Default for Cg {
+    fn default() -> Self {
+        Self {
+            wolfe: 0.4,
+            max_steps: 20,
+        }
+    }
+}
+
+/// When optimization stops. Every condition is checked after each step, and the first one met is
+/// the reason for stopping.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stop {
+    /// Stop once the norm of the gradient is at most this.
+    pub grad_norm: f64,
+
+    /// Stop once a step changes the objective by at most this fraction of it. When this is zero,
+    /// optimization only stops this way once the objective doesn't change at all.
+    pub rel_change: f64,
+
+    /// Stop after this many steps, or never if this is zero.
+    pub max_steps: usize,
+
+    /// Stop once this many seconds have passed, or never if this is zero.
+    pub time: f64,
+
+    /// Stop once the objective, not counting the reward for growth, is at most this.
+    pub penalty: Penalty,
+}
+
+impl Default for Stop {
+    fn default() -> Self {
+        Self {
+            grad_norm: 0.,
+            rel_change: 1e-9,
+            max_steps: 10_000,
+            time: 0.,
+            penalty: Penalty::Auto,
+        }
+    }
+}
+
+/// The most that the objective, not counting the reward for growth, can be for optimization to
+/// stop. In a scene, this is either a number or `false`.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Penalty {
+    /// Zero, so that optimization stops once the glyphs fit, unless growth is rewarded, in which
+    /// case there's still more to gain after that and this stop is off.
+    #[default]
+    Auto,
+
+    /// Never, so this stop is off.
+    Off,
+
+    /// This much.
+    At(f64),
+}
+
+impl<'de> Deserialize<'de> for Penalty {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            At(f64),
+            Flag(bool),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::At(penalty) => Ok(Penalty::At(penalty)),
+            Value::Flag(false) => Ok(Penalty::Off),
+            Value::Flag(true) => Err(D::Error::custom("expected a number or false")),
+        }
+    }
+}
+
+/// How optimization is interleaved with discrete moves, which swap the positions of two different
+/// letters or relocate a glyph to the spot with the most clearance.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Moves {
+    /// How many rounds of moves to make, each followed by optimizing again. At zero, there are no
+    /// moves, and only one optimization.
+    pub rounds: usize,
+
+    /// How many moves to try in each round.
+    pub tries: usize,
+
+    /// A move that makes the objective worse by `d` is accepted with probability
+    /// `exp(-d / temperature)`, so at zero, only moves that improve it are.
+    pub temperature: f64,
+
+    /// The factor by which the temperature drops after each round.
+    pub cooling: f64,
+}
+
+impl Default for Moves {
+    fn default() -> Self {
+        Self {
+            rounds: 0,
+            tries: 100,
+            temperature: 1.,
+            cooling: 0.8,
+        }
+    }
+}
+
+/// Return an error saying that `key` should be `what` unless `ok` holds for its `value`.
+fn expect(key: &str, value: f64, ok: impl Fn(f64) -> bool, what: &str) -> Result<(), String> {
+    if ok(value) {
+        Ok(())
+    } else {
+        Err(format!("{key} should be {what}, not {value}"))
+    }
+}
+
+impl Scene {
+    /// Read a scene from the TOML file at `path`, checking that every value makes sense.
+    ///
+    /// The font and the container's SVG file, if the scene names them, are relative to the
+    /// directory the scene is in.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut scene: Self =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        scene
+            .check()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(font) = &scene.glyphs.font {
+            scene.glyphs.font = Some(dir.join(font).display().to_string());
+        }
+        if let Some(svg) = &scene.container.svg {
+            if Path::new(svg).extension().is_some_and(|ext| ext == "svg") {
+                scene.container.svg = Some(dir.join(svg).display().to_string());
+            }
+        }
+        Ok(scene)
+    }
+
+    /// Return an error for the first value that doesn't make sense, if any.
+    ///
+    /// The comparisons are written so that NaN fails them.
+    pub fn check(&self) -> Result<(), String> {
+        let Self {
+            container,
+            glyphs,
+            layout,
+            sampling,
+            style,
+            optimizer,
+            stop,
+            moves,
+        } = self;
+        let positive = |x| x > 0.;
+        let nonnegative = |x| x >= 0.;
+        let fraction = |x| (0. ..1.).contains(&x);
+        let percent = |x| (0. ..=100.).contains(&x);
+
+        expect("container.height", container.height, positive, "positive")?;
+        expect("glyphs.size", glyphs.size, positive, "positive")?;
+
+        expect("layout.scale", layout.scale, positive, "positive")?;
+        expect("layout.gap", layout.gap, nonnegative, "nonnegative")?;
+        let degrees = |x| (0. ..180.).contains(&x);
+        expect(
+            "layout.tilt",
+            layout.tilt,
+            degrees,
+            "at least 0 and less than 180",
+        )?;
+        expect("layout.grow", layout.grow, |x| x >= 1., "at least 1")?;
+        expect("layout.growth", layout.growth, nonnegative, "nonnegative")?;
+
+        expect(
+            "sampling.tilt_step",
+            sampling.tilt_step,
+            positive,
+            "positive",
+        )?;
+        expect(
+            "sampling.grow_step",
+            sampling.grow_step,
+            |x| x > 1.,
+            "more than 1",
+        )?;
+        expect(
+            "sampling.tolerance",
+            sampling.tolerance,
+            positive,
+            "positive",
+        )?;
+
+        for (key, color) in [
+            ("style.container", &style.container),
+            ("style.stroke", &style.stroke),
+        ] {
+            if svgtypes::Color::from_str(color).is_err() {
+                return Err(format!("{key} should be a color, not {color:?}"));
+            }
+        }
+        expect(
+            "style.saturation",
+            style.saturation,
+            percent,
+            "from 0 to 100",
+        )?;
+        expect("style.value", style.value, percent, "from 0 to 100")?;
+        let opacity = |x| (0. ..=1.).contains(&x);
+        expect(
+            "style.stroke_opacity",
+            style.stroke_opacity,
+            opacity,
+            "from 0 to 1",
+        )?;
+        expect(
+            "style.stroke_width",
+            style.stroke_width,
+            nonnegative,
+            "nonnegative",
+        )?;
+
+        let names = ["lbfgs", "momentum", "adam", "cg"];
+        if !names.contains(&optimizer.name.as_str()) {
+            return Err(format!(
+                "optimizer.name should be one of lbfgs, momentum, adam or cg, not {:?}",
+                optimizer.name
+            ));
+        }
+        let Lbfgs {
+            m,
+            armijo,
+            wolfe,
+            min_interval,
+            max_steps,
+            epsd,
+            ..
+        } = optimizer.lbfgs;
+        let Cg {
+            wolfe: cg_wolfe,
+            max_steps: cg_max_steps,
+        } = optimizer.cg;
+        let at_least_one = |x| x >= 1.;
+        expect("optimizer.lbfgs.m", m as f64, at_least_one, "at least 1")?;
+        expect(
+            "optimizer.lbfgs.armijo",
+            armijo,
+            |x| 0. < x && x < 1.,
+            "between 0 and 1",
+        )?;
+        let wolfe_ok = |x| armijo < x && x < 1.;
+        let what = "between optimizer.lbfgs.armijo and 1";
+        expect("optimizer.lbfgs.wolfe", wolfe, wolfe_ok, what)?;
+        expect("optimizer.cg.wolfe", cg_wolfe, wolfe_ok, what)?;
+        expect(
+            "optimizer.lbfgs.min_interval",
+            min_interval,
+            positive,
+            "positive",
+        )?;
+        expect(
+            "optimizer.lbfgs.max_steps",
+            max_steps as f64,
+            at_least_one,
+            "at least 1",
+        )?;
+        expect(
+            "optimizer.cg.max_steps",
+            cg_max_steps as f64,
+            at_least_one,
+            "at least 1",
+        )?;
+        expect("optimizer.lbfgs.epsd", epsd, nonnegative, "nonnegative")?;
+
+        let optimizer::Momentum { rate, momentum } = optimizer.momentum;
+        expect("optimizer.momentum.rate", rate, positive, "positive")?;
+        let what = "at least 0 and less than 1";
+        expect("optimizer.momentum.momentum", momentum, fraction, what)?;
+        let optimizer::Adam {
+            rate,
+            beta1,
+            beta2,
+            eps,
+        } = optimizer.adam;
+        expect("optimizer.adam.rate", rate, positive, "positive")?;
+        expect("optimizer.adam.beta1", beta1, fraction, what)?;
+        expect("optimizer.adam.beta2", beta2, fraction, what)?;
+        expect("optimizer.adam.eps", eps, positive, "positive")?;
+
+        expect(
+            "moves.tries",
+            moves.tries as f64,
+            at_least_one,
+            "at least 1",
+        )?;
+        expect(
+            "moves.temperature",
+            moves.temperature,
+            nonnegative,
+            "nonnegative",
+        )?;
+        let cooling = |x| 0. < x && x <= 1.;
+        expect(
+            "moves.cooling",
+            moves.cooling,
+            cooling,
+            "above 0 and at most 1",
+        )?;
+
+        stop.check()
+    }
+}
+
+impl Stop {
+    /// Return an error for the first value that doesn't make sense, if any, as for `Scene::check`.
+    pub fn check(&self) -> Result<(), String> {
+        let nonnegative = |x| x >= 0.;
+        expect("stop.grad_norm", self.grad_norm, nonnegative, "nonnegative")?;
+        expect(
+            "stop.rel_change",
+            self.rel_change,
+            nonnegative,
+            "nonnegative",
+        )?;
+        expect("stop.time", self.time, nonnegative, "nonnegative")?;
+        if let Penalty::At(penalty) = self.penalty {
+            expect("stop.penalty", penalty, nonnegative, "nonnegative")?;
+        }
+        Ok(())
+    }
+
+    /// Return the most that the objective, not counting the reward for growth, can be for
+    /// optimization to stop with `layout`, if this stop is on.
+    pub fn penalty(&self, layout: &Layout) -> Option<f64> {
+        match self.penalty {
+            Penalty::Auto => (layout.grow == 1. || layout.growth == 0.).then_some(0.),
+            Penalty::Off => None,
+            Penalty::At(penalty) => Some(penalty),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Extract the filled outline of an SVG document as a single piece of SVG path data.
+//!
+//! This lets the container be any shape drawn in an SVG editor: every filled path in the document
+//! is flattened into one path with all transforms applied, so shapes like `<rect>` or `<circle>`,
+//! nested groups and relative path commands all come out in the same simple form.
+
+use resvg::{
+    tiny_skia::{PathSegment, Transform},
+    usvg::{
+        fontdb::Database, Group, Node, Options, PostProcessingSteps, Tree, TreeParsing,
+        TreePostProc,
+    },
+};
+use std::fmt::Write as _;
+
+/// Accumulates SVG path data in which every contour is explicitly closed and every curve is cubic,
+/// which is what `polygonize` expects.
+#[derive(Default)]
+pub struct PathData {
+    /// The start of the current contour, if there is one.
+    start: Option<(f64, f64)>,
+
+    /// The current point.
+    current: (f64, f64),
+
+    /// The path data so far.
+    d: String,
+}
+
+impl PathData {
+    pub fn move_to(&mut self, x: f64, y: f64) {
+        self.close();
+        write!(self.d, "M{x} {y}").unwrap();
+        self.start = Some((x, y));
+        self.current = (x, y);
+    }
+
+    pub fn line_to(&mut self, x: f64, y: f64) {
+        write!(self.d, "L{x} {y}").unwrap();
+        self.current = (x, y);
+    }
+
+    pub fn quad_to(&mut self, x1: f64, y1: f64, x: f64, y: f64) {
+        // degree elevation, so that every curve in the output is a cubic
+        let (x0, y0) = self.current;
+        self.curve_to(
+            x0 + 2. / 3. * (x1 - x0),
+            y0 + 2. / 3. * (y1 - y0),
+            x + 2. / 3. * (x1 - x),
+            y + 2. / 3. * (y1 - y),
+            x,
+            y,
+        );
+    }
+
+    pub fn curve_to(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        write!(self.d, "C{x1} {y1} {x2} {y2} {x} {y}").unwrap();
+        self.current = (x, y);
+    }
+
+    /// Close the current contour, if any, making sure it ends exactly where it started.
+    pub fn close(&mut self) {
+        if let Some((x, y)) = self.start.take() {
+            if self.current != (x, y) {
+                self.line_to(x, y);
+            }
+            self.d.push('Z');
+        }
+    }
+
+    pub fn finish(mut self) -> String {
+        self.close();
+        self.d
+    }
+}
+
+/// Append every filled path under `group` to `data`, transformed by `ts` after its own transform.
+/// Fails if a path doesn't stay finite.
+fn collect(group: &Group, ts: Transform, data: &mut PathData) -> Result<(), String> {
+    for node in &group.children {
+        match node {
+            Node::Group(group) => collect(group, ts, data)?,
+            Node::Path(path) if path.fill.is_some() => {
+                let path = path
+                    .data
+                    .as_ref()
+                    .clone()
+                    .transform(path.abs_transform.post_concat(ts))
+                    .ok_or("SVG paths should have finite coordinates")?;
+                for segment in path.segments() {
+                    match segment {
+                        PathSegment::MoveTo(p) => data.move_to(p.x.into(), p.y.into()),
+                        PathSegment::LineTo(p) => data.line_to(p.x.into(), p.y.into()),
+                        PathSegment::QuadTo(p1, p) => {
+                            data.quad_to(p1.x.into(), p1.y.into(), p.x.into(), p.y.into())
+                        }
+                        PathSegment::CubicTo(p1, p2, p) => data.curve_to(
+                            p1.x.into(),
+                            p1.y.into(),
+                            p2.x.into(),
+                            p2.y.into(),
+                            p.x.into(),
+                            p.y.into(),
+                        ),
+                        PathSegment::Close => data.close(),
+                    }
+                }
+                data.close();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Return path data for all the filled shapes in the SVG document `text`, along with its width.
+///
+/// The result is scaled to be `height` units tall, and translated so that its bounding box starts
+/// at the origin. Fails if the document can't be parsed or has nothing filled in it.
+pub fn outline(text: &str, height: f64) -> Result<(String, f64), String> {
+    let mut tree = Tree::from_str(text, &Options::default()).map_err(|e| e.to_string())?;
+    tree.postprocess(PostProcessingSteps::default(), &Database::new());
+    let bbox = tree
+        .root
+        .abs_bounding_box()
+        .ok_or("SVG should have at least one filled shape")?;
+    let k = height as f32 / bbox.height();
+    let ts = Transform::from_row(k, 0., 0., k, -k * bbox.x(), -k * bbox.y());
+    if !k.is_finite() {
+        return Err("SVG filled shapes should have some height".to_owned());
+    }
+    let mut data = PathData::default();
+    collect(&tree.root, ts, &mut data)?;
+    let d = data.finish();
+    if d.is_empty() {
+        return Err("SVG should have at least one filled shape".to_owned());
+    }
+    Ok((d, (k * bbox.width()).into()))
+}
+
+/// Like `outline`, but for a single piece of path data `d` instead of a whole SVG document.
+pub fn outline_path(d: &str, height: f64) -> Result<(String, f64), String> {
+    outline(
+        &format!(r#"<svg xmlns="http://www.w3.org/2000/svg"><path d="{d}" /></svg>"#),
+        height,
+    )
+}
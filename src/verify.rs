@@ -0,0 +1,269 @@
+//! Checking an arrangement independently of the approximations that optimizing it relies on.
+//!
+//! The outlines are flattened much more finely than for the Minkowski sums, and then placed just as
+//! they're drawn. The resulting polygons are compared directly: two glyphs overlap if any of their
+//! edges meet, or if either has a loop inside the other, and likewise a glyph is inside the
+//! container if none of their edges meet, all of its loops are inside the container, and none of
+//! the container's loops are inside it. Clearances are the shortest distances between edges.
+//!
+//! So that flattening can't hide an overlap, the glyphs are flattened conservatively, so that each
+//! polygon contains its glyph. The container's polygon instead cuts its corners by up to the
+//! tolerance, so a glyph only counts as inside it if it's farther than that from its edge. Either
+//! way, the distances reported are at most the true ones.
+
+use crate::{
+    polygonize, segment_distance, transformed, vec2, Flatten, Glyphs, Polygon, Shapes, Vec2,
+};
+use std::fmt;
+
+/// What `verify` found.
+pub struct Report {
+    /// The letter of each glyph.
+    pub letters: Vec<char>,
+
+    /// The maximum distance between an outline and the polygon edges approximating it.
+    pub tolerance: f64,
+
+    /// How far the glyphs are meant to stay from each other and from the container's edge.
+    pub gap: f64,
+
+    /// The shortest distance between two glyphs that don't overlap, and which glyphs they are.
+    pub clearance: Option<(f64, usize, usize)>,
+
+    /// The shortest distance from a glyph inside the container to its edge, and which glyph it is.
+    pub margin: Option<(f64, usize)>,
+
+    /// Each pair of glyphs that overlap, in order.
+    pub overlapping: Vec<(usize, usize)>,
+
+    /// Each glyph that isn't entirely inside the container, in order.
+    pub outside: Vec<usize>,
+}
+
+impl Report {
+    /// Whether the glyphs are all inside the container, without overlapping each other.
+    pub fn passed(&self) -> bool {
+        self.overlapping.is_empty() && self.outside.is_empty()
+    }
+
+    /// Whether every distance is at least the gap, which implies `passed` unless the gap is zero.
+    pub fn spaced(&self) -> bool {
+        let clear = self.clearance.is_none_or(|(d, ..)| d >= self.gap);
+        let margin = self.margin.is_none_or(|(d, _)| d >= self.gap);
+        self.passed() && clear && margin
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |i: usize| format!("{i} ({})", self.letters[i]);
+        let list = |names: Vec<String>| match names.is_empty() {
+            true => "none".to_owned(),
+            false => names.join(", "),
+        };
+        let short = |d: f64| match d < self.gap {
+            true => format!(", less than the gap of {}", self.gap),
+            false => String::new(),
+        };
+        let n = self.letters.len();
+        writeln!(f, "{n} glyphs, flattened to within {}", self.tolerance)?;
+        match self.clearance {
+            Some((d, i, j)) => writeln!(
+                f,
+                "closest glyphs: {} and {}, {d} apart{}",
+                name(i),
+                name(j),
+                short(d)
+            )?,
+            None => writeln!(f, "closest glyphs: none that don't overlap")?,
+        }
+        match self.margin {
+            Some((d, i)) => writeln!(
+                f,
+                "closest to the container's edge: {}, {d} away{}",
+                name(i),
+                short(d)
+            )?,
+            None => writeln!(f, "closest to the container's edge: none inside it")?,
+        }
+        let pairs = self.overlapping.iter();
+        let pairs = pairs.map(|&(i, j)| format!("{} and {}", name(i), name(j)));
+        writeln!(f, "overlapping: {}", list(pairs.collect()))?;
+        let outside = self.outside.iter().map(|&i| name(i));
+        writeln!(f, "outside the container: {}", list(outside.collect()))?;
+        match (self.passed(), self.spaced()) {
+            (false, _) => write!(f, "fail"),
+            (true, false) => write!(f, "pass, but closer than the gap"),
+            (true, true) => write!(f, "pass"),
+        }
+    }
+}
+
+/// The lower and upper corners of the bounding box of `polygon`.
+fn bounds(polygon: &Polygon) -> (Vec2, Vec2) {
+    let points = polygon.iter().flatten();
+    let mut lo = vec2(f64::INFINITY, f64::INFINITY);
+    let mut hi = vec2(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in points {
+        lo = vec2(lo.x.min(p.x), lo.y.min(p.y));
+        hi = vec2(hi.x.max(p.x), hi.y.max(p.y));
+    }
+    (lo, hi)
+}
+
+/// Return the distance between two bounding boxes, or zero if they overlap.
+fn bounds_distance((lo1, hi1): (Vec2, Vec2), (lo2, hi2): (Vec2, Vec2)) -> f64 {
+    let dx = (lo2.x - hi1.x).max(lo1.x - hi2.x).max(0.);
+    let dy = (lo2.y - hi1.y).max(lo1.y - hi2.y).max(0.);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Each edge of `polygon`, as its two ends.
+fn edges(polygon: &Polygon) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    polygon.iter().flat_map(|points| {
+        let n = points.len();
+        (0..n).map(move |i| (points[i], points[(i + 1) % n]))
+    })
+}
+
+/// Return twice the signed area of the triangle `a`, `b`, `c`, which is positive if it turns left.
+fn orient(a: Vec2, b: Vec2, c: Vec2) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Whether the line segments from `a` to `b` and from `c` to `d` have any point in common.
+fn meet(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let straddle = |u: f64, v: f64| (u <= 0. && v >= 0.) || (u >= 0. && v <= 0.);
+    let (o1, o2) = (orient(a, b, c), orient(a, b, d));
+    if o1 == 0. && o2 == 0. {
+        // on the same line, so they meet if their extents do
+        return a.x.min(b.x) <= c.x.max(d.x)
+            && c.x.min(d.x) <= a.x.max(b.x)
+            && a.y.min(b.y) <= c.y.max(d.y)
+            && c.y.min(d.y) <= a.y.max(b.y);
+    }
+    straddle(o1, o2) && straddle(orient(c, d, a), orient(c, d, b))
+}
+
+/// Return the shortest distance between the edges of `p` and `q`, which is zero if any meet, or
+/// else any distance at least `bound` if the shortest is.
+fn gap(p: &Polygon, q: &Polygon, bound: f64) -> f64 {
+    let mut best = bound;
+    for (a, b) in edges(p) {
+        for (c, d) in edges(q) {
+            if meet(a, b, c, d) {
+                return 0.;
+            }
+            let near = segment_distance(a, b, c)
+                .min(segment_distance(a, b, d))
+                .min(segment_distance(c, d, a))
+                .min(segment_distance(c, d, b));
+            best = best.min(near);
+        }
+    }
+    best
+}
+
+/// Whether `p` is inside `polygon`, according to the even-odd rule.
+fn inside(polygon: &Polygon, p: Vec2) -> bool {
+    polygon
+        .iter()
+        .fold(false, |inside, points| inside != crate::inside(points, p))
+}
+
+/// Whether any loop of `q` has a vertex inside `p`. If no edges of `p` and `q` meet, then this is
+/// the same as whether any loop of `q` is inside `p`.
+fn reaches_into(p: &Polygon, q: &Polygon) -> bool {
+    q.iter().any(|points| inside(p, points[0]))
+}
+
+/// Check that `glyphs` are inside the container and don't overlap, flattening the outlines in
+/// `shapes` to within `tolerance` of the container's units. Fails if an outline encloses nothing.
+pub fn verify(shapes: &Shapes, glyphs: &Glyphs, tolerance: f64) -> Result<Report, String> {
+    let Glyphs {
+        indices, coords, ..
+    } = glyphs;
+    let n = indices.len();
+    let layout = &shapes.layout;
+    // the largest scale magnifies the error the most
+    let largest = layout.scale * layout.grow;
+    let outlines: Vec<Polygon> = shapes
+        .glyphs
+        .iter()
+        .map(|(c, path)| {
+            let flatten = Flatten {
+                tolerance: tolerance / largest,
+                conservative: true,
+            };
+            let pieces = polygonize(path, flatten).map_err(|e| format!("glyph {c:?}: {e}"))?;
+            Ok(pieces.concat())
+        })
+        .collect::<Result<_, String>>()?;
+    let polygons: Vec<Polygon> = (0..n)
+        .map(|i| {
+            let angle = layout.tilt(coords[2 * n + i]);
+            let k = layout.scale * layout.grow(coords[3 * n + i]).exp();
+            let offset = vec2(coords[i], coords[n + i]);
+            let placed = transformed(angle, k, &outlines[indices[i]]);
+            placed
+                .into_iter()
+                .map(|points| points.into_iter().map(|v| v + offset).collect())
+                .collect()
+        })
+        .collect();
+    let boxes: Vec<_> = polygons.iter().map(bounds).collect();
+
+    // pairs whose boxes overlap come first, and are the only ones that can overlap themselves; after
+    // those, once a pair's boxes are farther apart than the closest glyphs so far, so are the rest
+    let mut pairs: Vec<(f64, usize, usize)> = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .map(|(i, j)| (bounds_distance(boxes[i], boxes[j]), i, j))
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut overlapping = vec![];
+    let mut clearance: Option<(f64, usize, usize)> = None;
+    for &(d, i, j) in &pairs {
+        let bound = clearance.map_or(f64::INFINITY, |(c, ..)| c);
+        if d > 0. && d >= bound {
+            break;
+        }
+        let (p, q) = (&polygons[i], &polygons[j]);
+        let g = gap(p, q, bound);
+        if g == 0. || reaches_into(q, p) || reaches_into(p, q) {
+            overlapping.push((i, j));
+        } else if g < bound {
+            clearance = Some((g, i, j));
+        }
+    }
+    overlapping.sort();
+
+    let flatten = Flatten {
+        tolerance,
+        conservative: false,
+    };
+    let container = polygonize(&shapes.container, flatten)
+        .map_err(|e| format!("container: {e}"))?
+        .concat();
+    let mut outside = vec![];
+    let mut margin: Option<(f64, usize)> = None;
+    for (i, p) in polygons.iter().enumerate() {
+        // the container's true edge may be up to `tolerance` closer than its polygon's
+        let g = gap(p, &container, f64::INFINITY) - tolerance;
+        let within = p.iter().all(|points| inside(&container, points[0]));
+        if g <= 0. || !within || reaches_into(p, &container) {
+            outside.push(i);
+        } else if margin.is_none_or(|(m, _)| g < m) {
+            margin = Some((g, i));
+        }
+    }
+
+    Ok(Report {
+        letters: indices.iter().map(|&j| shapes.glyphs[j].0).collect(),
+        tolerance,
+        gap: layout.gap,
+        clearance,
+        margin,
+        overlapping,
+        outside,
+    })
+}